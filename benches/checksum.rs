@@ -0,0 +1,18 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use woff2::checksum;
+
+fn bench_checksum(c: &mut Criterion) {
+    let mut group = c.benchmark_group("calculate_checksum");
+    for size in [1 << 10, 1 << 16, 1 << 22] {
+        // include an unaligned tail
+        let data: Vec<u8> = (0..size + 3).map(|i| (i * 31) as u8).collect();
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &data, |b, data| {
+            b.iter(|| checksum::calculate_checksum(black_box(data)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_checksum);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+//! Set `WOFF2_BENCH_FONT` to the path of a WOFF2 font (a large CJK font shows the difference
+//! best) to benchmark decoding it instead of the bundled font.
+
+use std::io::Cursor;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use woff2::decode::{convert_woff2_to_ttf_with_options, DecodeOptions};
+
+fn bench_decode(c: &mut Criterion) {
+    let font = match std::env::var_os("WOFF2_BENCH_FONT") {
+        Some(path) => std::fs::read(path).expect("failed to read WOFF2_BENCH_FONT"),
+        None => include_bytes!("../src/test_resources/lato-v22-latin-regular.woff2").to_vec(),
+    };
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Bytes(font.len() as u64));
+    for compute_checksums in [true, false] {
+        let options = DecodeOptions::new()
+            .compute_checksums(compute_checksums)
+            .build();
+        group.bench_with_input(
+            BenchmarkId::new("compute_checksums", compute_checksums),
+            &font,
+            |b, font| {
+                b.iter(|| {
+                    convert_woff2_to_ttf_with_options(&mut Cursor::new(black_box(font)), &options)
+                        .unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_decode);
+criterion_main!(benches);
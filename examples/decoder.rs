@@ -1,8 +1,11 @@
-use std::{io, path::PathBuf};
+use std::{
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
 
 use clap::Parser;
 use thiserror::Error;
-use woff2::decode::{convert_woff2_to_ttf, DecodeError};
+use woff2::decode::{convert_woff2_to_ttf, extract_metadata, list_tables, DecodeError};
 
 #[derive(Debug, Error)]
 enum Error {
@@ -10,18 +13,69 @@ enum Error {
     Woff(#[from] DecodeError),
     #[error(transparent)]
     Io(#[from] io::Error),
+    #[error("The decoded font is invalid: {0}")]
+    Validation(#[from] ttf_parser::FaceParsingError),
 }
 
 #[derive(Debug, Parser)]
 struct Args {
+    /// The WOFF2 font to read, or `-` for stdin
     in_path: PathBuf,
+    /// Where to write the decoded font, or `-` for stdout
     out_path: PathBuf,
+    /// Also write the font's XML metadata to this file
+    #[clap(long, value_name = "PATH")]
+    metadata: Option<PathBuf>,
+    /// Print the tags of the font's tables, in table directory order
+    #[clap(long)]
+    print_tables: bool,
+    /// Check that the decoded font can be parsed before writing it
+    #[clap(long)]
+    validate: bool,
+}
+
+/// Whether `path` stands for stdin or stdout
+fn is_std_stream(path: &Path) -> bool {
+    path.as_os_str() == "-"
 }
 
 fn main() -> Result<(), Error> {
     let args = Args::parse();
-    let input = std::fs::read(args.in_path)?;
+    // the whole font is needed up front, since the table directory refers back into it
+    let input = if is_std_stream(&args.in_path) {
+        let mut input = Vec::new();
+        io::stdin().read_to_end(&mut input)?;
+        input
+    } else {
+        std::fs::read(&args.in_path)?
+    };
+    let to_stdout = is_std_stream(&args.out_path);
+    if args.print_tables {
+        for tag in list_tables(&mut io::Cursor::new(&input))? {
+            // keep stdout for the font itself
+            if to_stdout {
+                eprintln!("{}", String::from_utf8_lossy(&tag.0));
+            } else {
+                println!("{}", String::from_utf8_lossy(&tag.0));
+            }
+        }
+    }
+    if let Some(metadata_path) = args.metadata {
+        match extract_metadata(&mut io::Cursor::new(&input))? {
+            Some(metadata) => std::fs::write(metadata_path, metadata)?,
+            None => eprintln!("The font has no metadata"),
+        }
+    }
     let ttf = convert_woff2_to_ttf(&mut io::Cursor::new(input))?;
-    std::fs::write(args.out_path, ttf)?;
+    if args.validate {
+        ttf_parser::Face::from_slice(&ttf, 0)?;
+    }
+    if to_stdout {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&ttf)?;
+        stdout.flush()?;
+    } else {
+        std::fs::write(args.out_path, ttf)?;
+    }
     Ok(())
 }
@@ -0,0 +1,79 @@
+//! Compares the peak heap usage of regular and streaming decoding.
+//!
+//! Run with `cargo run --release --example peak_memory --features streaming -- <font.woff2>`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    io,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use clap::Parser;
+use woff2::decode::{
+    convert_woff2_to_ttf_streaming, convert_woff2_to_ttf_with_options, DecodeOptions,
+};
+
+/// Keeps track of the current and peak number of allocated bytes.
+struct CountingAllocator {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let current = self.current.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            self.peak.fetch_max(current, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        self.current.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator {
+    current: AtomicUsize::new(0),
+    peak: AtomicUsize::new(0),
+};
+
+/// Runs `f`, returning the peak heap usage on top of what was allocated before.
+fn peak_usage(f: impl FnOnce()) -> usize {
+    let before = ALLOCATOR.current.load(Ordering::Relaxed);
+    ALLOCATOR.peak.store(before, Ordering::Relaxed);
+    f();
+    ALLOCATOR.peak.load(Ordering::Relaxed) - before
+}
+
+#[derive(Debug, Parser)]
+struct Args {
+    in_path: PathBuf,
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+    let input = std::fs::read(args.in_path)?;
+    let options = DecodeOptions::default();
+
+    let mut output_len = 0;
+    let regular = peak_usage(|| {
+        let ttf = convert_woff2_to_ttf_with_options(&mut io::Cursor::new(&input), &options)
+            .expect("failed to decode font");
+        output_len = ttf.len();
+    });
+    let streaming = peak_usage(|| {
+        convert_woff2_to_ttf_streaming(&mut io::Cursor::new(&input), &options)
+            .expect("failed to decode font");
+    });
+
+    println!("input:     {:>12} bytes", input.len());
+    println!("output:    {:>12} bytes", output_len);
+    println!("regular:   {:>12} bytes peak", regular);
+    println!("streaming: {:>12} bytes peak", streaming);
+    Ok(())
+}
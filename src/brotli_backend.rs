@@ -0,0 +1,72 @@
+//! Brotli decompression of the WOFF2 compressed stream
+
+use std::io::{Read, Write};
+
+/// A brotli decompressor used to decompress the WOFF2 table data.
+///
+/// Implement this to use an alternative (e.g. hardware-accelerated) brotli implementation, and
+/// pass it to the decoder through [`DecodeOptions`](crate::decode::DecodeOptions). Options are
+/// shared between threads, so backends must be `Send + Sync`.
+pub trait BrotliBackend: Send + Sync {
+    /// Decompresses the brotli stream read from `input`, writing the result to `output`.
+    ///
+    /// `input` ends with the end of the compressed stream. Writing to `output` fails as soon as
+    /// there's more data than the font's table directory says, so a stream that decompresses to
+    /// far more (a decompression bomb) is stopped early; the error should be returned as is.
+    fn decompress(&self, input: &mut dyn Read, output: &mut dyn Write) -> std::io::Result<()>;
+
+    /// Returns a reader that decompresses the brotli stream read from `input` as it goes.
+    ///
+    /// The default implementation decompresses the whole stream with
+    /// [`decompress`](Self::decompress) first, which doesn't save any memory.
+    #[cfg(feature = "streaming")]
+    fn decompress_reader<'a>(
+        &self,
+        input: &'a mut dyn Read,
+    ) -> std::io::Result<Box<dyn Read + 'a>> {
+        let mut output = Vec::new();
+        self.decompress(input, &mut output)?;
+        Ok(Box::new(std::io::Cursor::new(output)))
+    }
+}
+
+#[cfg(not(any(feature = "backend-brotli", feature = "backend-brotli-decompressor")))]
+compile_error!(
+    "one of the features `backend-brotli` or `backend-brotli-decompressor` must be enabled"
+);
+
+/// The default backend, using the `brotli` crate, or the `brotli-decompressor` crate if only the
+/// `backend-brotli-decompressor` feature is enabled.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultBrotliBackend;
+
+impl BrotliBackend for DefaultBrotliBackend {
+    fn decompress(&self, input: &mut dyn Read, output: &mut dyn Write) -> std::io::Result<()> {
+        // `&mut dyn Read` and `&mut dyn Write` are themselves a (sized) reader and writer
+        #[cfg(feature = "backend-brotli")]
+        return brotli::BrotliDecompress(&mut &mut *input, &mut &mut *output);
+        #[cfg(all(
+            feature = "backend-brotli-decompressor",
+            not(feature = "backend-brotli")
+        ))]
+        return brotli_decompressor::BrotliDecompress(&mut &mut *input, &mut &mut *output);
+    }
+
+    #[cfg(feature = "streaming")]
+    fn decompress_reader<'a>(
+        &self,
+        input: &'a mut dyn Read,
+    ) -> std::io::Result<Box<dyn Read + 'a>> {
+        const BUFFER_SIZE: usize = 4096;
+        #[cfg(feature = "backend-brotli")]
+        return Ok(Box::new(brotli::Decompressor::new(input, BUFFER_SIZE)));
+        #[cfg(all(
+            feature = "backend-brotli-decompressor",
+            not(feature = "backend-brotli")
+        ))]
+        return Ok(Box::new(brotli_decompressor::Decompressor::new(
+            input,
+            BUFFER_SIZE,
+        )));
+    }
+}
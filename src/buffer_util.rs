@@ -108,6 +108,50 @@ where
     }
 }
 
+// `put_255_u16` is only used by tests until the encoder can transform `glyf`, and the encoder
+// isn't built without the brotli crate
+#[cfg_attr(not(test), allow(dead_code))]
+pub trait BufMutExt {
+    /// Writes a `u16` using the 255UInt16 encoding, choosing the shortest form.
+    fn put_255_u16(&mut self, value: u16);
+    /// Writes a `u32` using the minimal UIntBase128 encoding.
+    fn put_base_128(&mut self, value: u32);
+}
+
+impl<B> BufMutExt for B
+where
+    B: BufMut,
+{
+    fn put_255_u16(&mut self, value: u16) {
+        const ONE_MORE_BYTE_CODE_1: u8 = 255;
+        const ONE_MORE_BYTE_CODE_2: u8 = 254;
+        const WORD_CODE: u8 = 253;
+        const LOWEST_UCODE: u16 = 253;
+        if value < LOWEST_UCODE {
+            self.put_u8(value as u8);
+        } else if value < 2 * LOWEST_UCODE {
+            self.put_u8(ONE_MORE_BYTE_CODE_1);
+            self.put_u8((value - LOWEST_UCODE) as u8);
+        } else if value < 3 * LOWEST_UCODE + 3 {
+            self.put_u8(ONE_MORE_BYTE_CODE_2);
+            self.put_u8((value - 2 * LOWEST_UCODE) as u8);
+        } else {
+            self.put_u8(WORD_CODE);
+            self.put_u16(value);
+        }
+    }
+
+    fn put_base_128(&mut self, value: u32) {
+        // number of 7-bit groups needed, at least one so that 0 is written as a single byte
+        let num_bytes = (32 - value.leading_zeros()).div_ceil(7).max(1);
+        for i in (0..num_bytes).rev() {
+            let byte = ((value >> (i * 7)) & 0x7F) as u8;
+            // continuation bit on every byte but the last
+            self.put_u8(if i == 0 { byte } else { byte | 0x80 });
+        }
+    }
+}
+
 /// Pads the buffer with zeros so its lenght is a multiple of four
 pub fn pad_to_multiple_of_four(buffer: &mut Vec<u8>) {
     if buffer.len() & 3 != 0 {
@@ -120,7 +164,9 @@ pub fn pad_to_multiple_of_four(buffer: &mut Vec<u8>) {
 mod tests {
     use std::io::Cursor;
 
-    use super::BufExt;
+    use bytes::Buf;
+
+    use super::{BufExt, BufMutExt};
 
     fn test_get_255_u16(expected: u16, data: &[u8]) {
         let mut buf = Cursor::new(data);
@@ -145,6 +191,51 @@ mod tests {
         test_get_255_u16(506, &[253, 1, 250]);
     }
 
+    fn round_trip_255_u16(value: u16) -> usize {
+        let mut encoded = Vec::new();
+        encoded.put_255_u16(value);
+        let mut buf = Cursor::new(&encoded);
+        assert_eq!(value, buf.try_get_255_u16().unwrap());
+        assert_eq!(encoded.len() as u64, buf.position());
+        encoded.len()
+    }
+
+    #[test]
+    fn u255_uint_16_round_trip() {
+        for value in 0..=1024 {
+            round_trip_255_u16(value);
+        }
+        for value in [u16::MAX - 1, u16::MAX] {
+            round_trip_255_u16(value);
+        }
+    }
+
+    #[test]
+    fn u255_uint_16_round_trip_ambiguous_range() {
+        // 253..=505 could be written as a word, but the one-more-byte forms are shorter
+        for value in 253..=505 {
+            assert_eq!(2, round_trip_255_u16(value));
+        }
+        assert_eq!(2, round_trip_255_u16(761));
+        assert_eq!(3, round_trip_255_u16(762));
+    }
+
+    #[test]
+    fn read_across_chunks() {
+        let data = [253, 1, 250, 0x81, 0, b'g', b'l', b'y', b'f', 1, 2, 3, 4, 5];
+        for split in 0..data.len() {
+            let (first, second) = data.split_at(split);
+            let mut buf = first.chain(second);
+            assert_eq!(506, buf.try_get_255_u16().unwrap());
+            assert_eq!(128, buf.try_get_base_128().unwrap());
+            assert_eq!(b"glyf", &buf.try_get_four_cc().unwrap().0);
+            let mut dest = Vec::new();
+            buf.try_copy_to_buf(&mut dest, 5).unwrap();
+            assert_eq!(dest, [1, 2, 3, 4, 5]);
+            assert!(!buf.has_remaining());
+        }
+    }
+
     #[test]
     fn uint_base_128_0_works() {
         let mut buf = Cursor::new(&[0]);
@@ -159,6 +250,30 @@ mod tests {
         assert_eq!(128, result.unwrap());
     }
 
+    #[test]
+    fn uint_base_128_round_trip() {
+        for (value, expected_len) in [
+            (0, 1),
+            (1, 1),
+            (127, 1),
+            (128, 2),
+            (16383, 2),
+            (16384, 3),
+            (60716, 3),
+            (0x0FFF_FFFF, 4),
+            (0x1000_0000, 5),
+            (u32::MAX, 5),
+        ] {
+            let mut encoded = Vec::new();
+            encoded.put_base_128(value);
+            assert_eq!(expected_len, encoded.len(), "length of encoded {}", value);
+            assert_ne!(0x80, encoded[0], "leading zero in encoded {}", value);
+            let mut buf = Cursor::new(&encoded);
+            assert_eq!(value, buf.try_get_base_128().unwrap());
+            assert_eq!(encoded.len() as u64, buf.position());
+        }
+    }
+
     #[test]
     fn try_copy_to_buf() {
         use bytes::{Buf, BufMut};
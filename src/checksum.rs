@@ -1,3 +1,22 @@
+//! OpenType table and font checksums
+//!
+//! Each table record in an OpenType font stores the checksum of its table. The `head` table also
+//! has a `checkSumAdjustment` field, which is set so that the checksum of the entire font comes out
+//! to `0xB1B0AFBA`. After editing a font, fix it up by:
+//!
+//! 1. setting `checkSumAdjustment` to zero with [`set_checksum_adjustment`],
+//! 2. recalculating the checksums of the changed tables (including `head`) with
+//!    [`calculate_checksum`] and updating their table records, and then
+//! 3. storing [`calculate_font_checksum_adjustment`] of the whole font in `checkSumAdjustment`.
+//!
+//! ```
+//! use woff2::checksum::{calculate_checksum, calculate_font_checksum_adjustment};
+//!
+//! let font = [0x00, 0x01, 0x00, 0x00, 0x00, 0x00];
+//! assert_eq!(0x0001_0000, calculate_checksum(&font));
+//! assert_eq!(0xB1AF_AFBA, calculate_font_checksum_adjustment(&font));
+//! ```
+
 use std::num::Wrapping;
 
 use bytes::BufMut;
@@ -8,37 +27,54 @@ use thiserror::Error;
 /// If the data is not a multiple of 4 bytes long, it is treated as if padded with zeroes at the
 /// end.
 pub fn calculate_checksum(data: &[u8]) -> u32 {
-    let chunks = data.chunks_exact(4);
-    // maybe there's a more elegant way of doing this, but i think this is alright
-    let last = u32::from_be_bytes(match chunks.remainder() {
-        &[] => [0; 4],
-        &[b0] => [b0, 0, 0, 0],
-        &[b0, b1] => [b0, b1, 0, 0],
-        &[b0, b1, b2] => [b0, b1, b2, 0],
-        _ => unreachable!("ChunksExact::remainder is guaranteed to return a slice of length < n"),
-    });
-    (chunks
-        // we can get rid of this `try_into().unwrap()` once `&[T]::array_chunks` is stabilized
-        .map(|slice| Wrapping(u32::from_be_bytes(slice.try_into().unwrap())))
-        .sum::<Wrapping<u32>>()
-        + Wrapping(last))
-    .0
+    // Summing into eight independent lanes lets the compiler vectorize the main loop. Addition
+    // modulo 2^32 is associative, so the result is the same as summing the words in order.
+    let mut lanes = [Wrapping(0u32); 8];
+    let mut blocks = data.chunks_exact(32);
+    for block in &mut blocks {
+        for (lane, word) in lanes.iter_mut().zip(block.chunks_exact(4)) {
+            // we can get rid of this `try_into().unwrap()` once `&[T]::array_chunks` is stabilized
+            *lane += u32::from_be_bytes(word.try_into().unwrap());
+        }
+    }
+    let mut sum = lanes.iter().sum::<Wrapping<u32>>();
+
+    let mut words = blocks.remainder().chunks_exact(4);
+    for word in &mut words {
+        sum += u32::from_be_bytes(word.try_into().unwrap());
+    }
+    // the tail is treated as if padded with zeroes
+    let mut last = [0; 4];
+    last[..words.remainder().len()].copy_from_slice(words.remainder());
+    (sum + Wrapping(u32::from_be_bytes(last))).0
 }
 
 #[derive(Debug, Error)]
 pub enum ChecksumError {
     #[error("Truncated `head` table")]
     Truncated,
+    #[error("Invalid magic number in `head` table")]
+    BadHeadMagic,
 }
 
+/// The value of the `magicNumber` field in the `head` table
+const HEAD_MAGIC_NUMBER: u32 = 0x5F0F3CF5;
+
 /// Sets the `checksum_adjustment` field in the `head` table to the specified value.
+///
+/// The table's `magicNumber` is checked first, so that a table mislabeled as `head` isn't
+/// corrupted.
 pub fn set_checksum_adjustment(head_table: &mut [u8], value: u32) -> Result<(), ChecksumError> {
     // table version: 4 bytes
     // font revision: 4 bytes
     // checksum adjustment: 4 bytes
-    if head_table.len() < 12 {
+    // magic number: 4 bytes
+    if head_table.len() < 16 {
         return Err(ChecksumError::Truncated);
     }
+    if head_table[12..16] != HEAD_MAGIC_NUMBER.to_be_bytes() {
+        return Err(ChecksumError::BadHeadMagic);
+    }
     let mut checksum_field = &mut head_table[8..12];
     checksum_field.put_u32(value);
     Ok(())
@@ -50,3 +86,79 @@ pub fn calculate_font_checksum_adjustment(font: &[u8]) -> u32 {
     let checksum = calculate_checksum(font);
     CHECKSUM_MINUEND.wrapping_sub(checksum)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::num::Wrapping;
+
+    use proptest::prelude::*;
+
+    use super::{calculate_checksum, set_checksum_adjustment, ChecksumError};
+
+    /// Straightforward word-at-a-time checksum to compare against.
+    fn reference_checksum(data: &[u8]) -> u32 {
+        data.chunks(4)
+            .map(|word| {
+                let mut padded = [0; 4];
+                padded[..word.len()].copy_from_slice(word);
+                Wrapping(u32::from_be_bytes(padded))
+            })
+            .sum::<Wrapping<u32>>()
+            .0
+    }
+
+    #[test]
+    fn checksum_of_empty_data() {
+        assert_eq!(0, calculate_checksum(&[]));
+    }
+
+    #[test]
+    fn checksum_pads_tail_with_zeroes() {
+        assert_eq!(0x0102_0300, calculate_checksum(&[1, 2, 3]));
+        assert_eq!(
+            0x0000_0001 + 0x0200_0000,
+            calculate_checksum(&[0, 0, 0, 1, 2])
+        );
+    }
+
+    #[test]
+    fn checksum_wraps() {
+        assert_eq!(0xFFFF_FFFE, calculate_checksum(&[0xFF; 8]));
+    }
+
+    #[test]
+    fn set_checksum_adjustment_checks_magic() {
+        let mut head = [0u8; 54];
+        head[12..16].copy_from_slice(&[0x5F, 0x0F, 0x3C, 0xF5]);
+        set_checksum_adjustment(&mut head, 0x1234_5678).unwrap();
+        assert_eq!(head[8..12], [0x12, 0x34, 0x56, 0x78]);
+
+        let mut not_head = [0u8; 54];
+        assert!(matches!(
+            set_checksum_adjustment(&mut not_head, 0x1234_5678),
+            Err(ChecksumError::BadHeadMagic)
+        ));
+        assert_eq!(not_head, [0u8; 54]);
+
+        assert!(matches!(
+            set_checksum_adjustment(&mut head[..14], 0),
+            Err(ChecksumError::Truncated)
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn checksum_matches_reference(data in proptest::collection::vec(any::<u8>(), 0..300)) {
+            prop_assert_eq!(reference_checksum(&data), calculate_checksum(&data));
+        }
+
+        #[test]
+        fn checksum_matches_reference_for_every_alignment(
+            data in proptest::collection::vec(any::<u8>(), 67),
+            tail in 0usize..4,
+        ) {
+            let data = &data[..64 + tail];
+            prop_assert_eq!(reference_checksum(data), calculate_checksum(data));
+        }
+    }
+}
@@ -1,16 +1,35 @@
 //! Interface for decoding WOFF2 files
 
-use bytes::Buf;
+#[cfg(feature = "std")]
+use std::path::Path;
+use std::{
+    io::{Read, Write},
+    sync::Arc,
+};
+
+use bytes::{Buf, Bytes};
+use four_cc::FourCC;
 use thiserror::Error;
 
 use crate::{
-    checksum::{calculate_font_checksum_adjustment, set_checksum_adjustment, ChecksumError},
+    brotli_backend::{BrotliBackend, DefaultBrotliBackend},
+    checksum::{calculate_checksum, ChecksumError},
+    glyf_decoder::{
+        decode_glyf_table, glyph_range, glyphs, rebuild_loca, DecodedGlyf,
+        DEFAULT_MAX_POINTS_PER_GLYPH,
+    },
     magic_numbers::{TTF_CFF_FLAVOR, TTF_COLLECTION_FLAVOR, TTF_TRUE_TYPE_FLAVOR, WOFF2_SIGNATURE},
-    ttf_header::{calculate_header_size, TableDirectory},
+    sfnt::write_table_directory,
+    tag::display_tag,
+    ttf_header::{calculate_header_size, TableDirectory, TableRecord},
     woff2::{
         collection_directory::{CollectionHeader, CollectionHeaderError},
-        header::{Woff2Header, Woff2HeaderError},
-        table_directory::{TableDirectoryError, Woff2TableDirectory, WriteTablesError, HEAD_TAG},
+        header::{Woff2Header, Woff2HeaderError, HEADER_SIZE},
+        table_directory::{
+            calculate_table_checksums, TableDirectoryEntry, TableDirectoryError,
+            Woff2TableDirectory, WriteTablesError, CMAP_TAG, DSIG_TAG, GLYF_TAG, HEAD_TAG,
+            LOCA_TAG, MAXP_TAG,
+        },
     },
 };
 
@@ -20,44 +39,252 @@ pub enum DecodeError {
     Invalid(String),
     #[error("Unsupported feature {0}")]
     Unsupported(&'static str),
+    #[error("Invalid Woff2 header")]
+    Header(#[from] Woff2HeaderError),
+    #[error("Invalid Woff2 table directory")]
+    TableDirectory(#[from] TableDirectoryError),
+    #[error("Invalid Woff2 collection directory")]
+    CollectionDirectory(#[from] CollectionHeaderError),
+    #[error("Invalid font tables")]
+    Tables(#[source] WriteTablesError),
+    #[error("Invalid `head` table")]
+    Checksum(#[from] ChecksumError),
+    #[error("Failed to decompress font data")]
+    Decompression(#[source] std::io::Error),
+    #[error("Decompressed font data is too large ({size} bytes, limit is {limit} bytes)")]
+    TooLarge { size: usize, limit: usize },
+    #[error(
+        "Font flavor `{}` doesn't match the expected `{}`",
+        display_tag(*.flavor),
+        display_tag(*.expected)
+    )]
+    BadFlavor { flavor: FourCC, expected: FourCC },
+    #[error("Failed to read font file")]
+    Io(#[source] std::io::Error),
+    #[error("Failed to write the decoded font")]
+    Write(#[source] std::io::Error),
 }
 
-impl From<ChecksumError> for DecodeError {
-    fn from(e: ChecksumError) -> Self {
-        DecodeError::Invalid(e.to_string())
+impl DecodeError {
+    /// Whether the font uses a feature this crate doesn't support, so another decoder might still
+    /// be able to read it.
+    pub fn is_unsupported(&self) -> bool {
+        matches!(self, DecodeError::Unsupported(_))
     }
-}
 
-impl From<CollectionHeaderError> for DecodeError {
-    fn from(e: CollectionHeaderError) -> Self {
-        DecodeError::Invalid(e.to_string())
+    /// Whether the font itself is malformed, as opposed to unsupported, over the size limit, or
+    /// unreadable.
+    pub fn is_corrupt(&self) -> bool {
+        match self {
+            DecodeError::Invalid(_)
+            | DecodeError::Header(_)
+            | DecodeError::TableDirectory(_)
+            | DecodeError::CollectionDirectory(_)
+            | DecodeError::Tables(_)
+            | DecodeError::Checksum(_)
+            | DecodeError::Decompression(_) => true,
+            DecodeError::Unsupported(_)
+            | DecodeError::TooLarge { .. }
+            | DecodeError::BadFlavor { .. }
+            | DecodeError::Io(_)
+            | DecodeError::Write(_) => false,
+        }
     }
 }
 
-impl From<TableDirectoryError> for DecodeError {
-    fn from(e: TableDirectoryError) -> Self {
-        DecodeError::Invalid(e.to_string())
+impl From<WriteTablesError> for DecodeError {
+    fn from(e: WriteTablesError) -> Self {
+        match e {
+            WriteTablesError::Unsupported(e) => DecodeError::Unsupported(e),
+            WriteTablesError::TableDirectory(e) => DecodeError::TableDirectory(e),
+            #[cfg(feature = "streaming")]
+            WriteTablesError::Decompression(e) => DecodeError::Decompression(e),
+            _ => DecodeError::Tables(e),
+        }
     }
 }
 
-impl From<Woff2HeaderError> for DecodeError {
-    fn from(e: Woff2HeaderError) -> Self {
-        DecodeError::Invalid(e.to_string())
+/// The default limit on the size of the decompressed font data (256 MiB).
+pub const DEFAULT_MAX_DECOMPRESSED_SIZE: usize = 256 << 20;
+
+/// Options controlling how WOFF2 files are decoded.
+///
+/// ```
+/// use woff2::decode::DecodeOptions;
+///
+/// let options = DecodeOptions::new()
+///     .max_decompressed_size(32 << 20)
+///     .strict(true)
+///     .build();
+/// assert_eq!(32 << 20, options.max_decompressed_size);
+/// ```
+#[derive(Clone)]
+pub struct DecodeOptions {
+    /// The brotli implementation used to decompress the table data
+    pub brotli_backend: Arc<dyn BrotliBackend>,
+    /// Fonts whose table data decompresses to more than this many bytes are rejected before
+    /// decompressing. Decompression also stops as soon as the data is longer than the table
+    /// directory says, whatever this is.
+    pub max_decompressed_size: usize,
+    /// Simple glyphs with more points than this are rejected while reconstructing a transformed
+    /// `glyf` table
+    pub max_points_per_glyph: u16,
+    /// Whether to calculate the table checksums and the `checkSumAdjustment` in the `head` table.
+    /// If not, they're left as zero, which is faster for callers that don't need valid checksums.
+    /// Checksums aren't verified either in that case.
+    pub compute_checksums: bool,
+    /// Whether to ignore the `total_compressed_size` in the header, and instead take the
+    /// compressed stream to run up to the next block (or the end of the file). Some fonts in the
+    /// wild (e.g. a reported Bootstrap Icons font) declare the wrong size but decode fine
+    /// otherwise.
+    pub lenient_compressed_size: bool,
+    /// Whether to rebuild a transformed `loca` table whose `glyf` table isn't transformed, as some
+    /// non-conformant encoders write them, by walking the glyphs in `glyf`. This only works for a
+    /// single font with no empty glyphs, and not when streaming or in strict mode.
+    pub lenient_loca_transform: bool,
+    /// Whether to null out the `DSIG` table of a single font, since reconstructing the font
+    /// invalidates its signature. The table keeps its length, but has no signatures left.
+    pub strip_dsig: bool,
+    /// Whether to lay out the tables of the decoded font in the order they're stored in the WOFF2
+    /// file, for comparing the output with other decoders byte for byte. They are anyway, except
+    /// that a `loca` table is normally moved up to directly follow its `glyf` table. The table
+    /// directory is sorted by tag either way, so the font is still valid. The streaming decoder
+    /// always uses this order.
+    pub physical_table_order: bool,
+    /// The flavor (sfnt version) the font must have, e.g. `0x00010000` for TrueType outlines, so
+    /// that CFF fonts or collections can be turned away before decompressing anything
+    pub expected_flavor: Option<FourCC>,
+    /// Whether to copy transformed tables (`glyf`, `loca` and `hmtx`) to the output as they're
+    /// stored in the WOFF2 file, instead of reconstructing them. This is a debugging aid for
+    /// comparing the transformed and reconstructed tables; the output is **not** a valid font, as
+    /// those tables keep their tags but hold the transformed data (a transformed `loca` table is
+    /// empty).
+    pub skip_glyf_transform: bool,
+    /// Whether to reject fonts that real-world decoders would usually accept, such as fonts whose
+    /// `total_sfnt_size` doesn't match the reconstructed font, or fonts without a `head` or `cmap`
+    /// table
+    pub strict: bool,
+}
+
+impl DecodeOptions {
+    /// Creates a builder starting from the default options.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new() -> DecodeOptionsBuilder {
+        DecodeOptionsBuilder::default()
     }
 }
 
-impl From<WriteTablesError> for DecodeError {
-    fn from(e: WriteTablesError) -> Self {
-        match e {
-            WriteTablesError::Unsupported(e) => DecodeError::Unsupported(e),
-            _ => DecodeError::Invalid(e.to_string()),
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        DecodeOptions {
+            brotli_backend: Arc::new(DefaultBrotliBackend),
+            max_decompressed_size: DEFAULT_MAX_DECOMPRESSED_SIZE,
+            max_points_per_glyph: DEFAULT_MAX_POINTS_PER_GLYPH,
+            compute_checksums: true,
+            lenient_compressed_size: false,
+            lenient_loca_transform: false,
+            strip_dsig: true,
+            physical_table_order: false,
+            expected_flavor: None,
+            skip_glyf_transform: false,
+            strict: false,
         }
     }
 }
 
-impl From<std::io::Error> for DecodeError {
-    fn from(e: std::io::Error) -> Self {
-        DecodeError::Invalid(e.to_string())
+impl std::fmt::Debug for DecodeOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecodeOptions")
+            .field("max_decompressed_size", &self.max_decompressed_size)
+            .field("max_points_per_glyph", &self.max_points_per_glyph)
+            .field("compute_checksums", &self.compute_checksums)
+            .field("lenient_compressed_size", &self.lenient_compressed_size)
+            .field("lenient_loca_transform", &self.lenient_loca_transform)
+            .field("strip_dsig", &self.strip_dsig)
+            .field("physical_table_order", &self.physical_table_order)
+            .field("expected_flavor", &self.expected_flavor)
+            .field("skip_glyf_transform", &self.skip_glyf_transform)
+            .field("strict", &self.strict)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Builder for [`DecodeOptions`], created by [`DecodeOptions::new`].
+#[derive(Clone, Debug, Default)]
+pub struct DecodeOptionsBuilder {
+    options: DecodeOptions,
+}
+
+impl DecodeOptionsBuilder {
+    /// Sets the brotli implementation used to decompress the table data.
+    pub fn brotli_backend(mut self, backend: Arc<dyn BrotliBackend>) -> Self {
+        self.options.brotli_backend = backend;
+        self
+    }
+
+    /// Sets the largest decompressed size, in bytes, that will be accepted.
+    pub fn max_decompressed_size(mut self, max_decompressed_size: usize) -> Self {
+        self.options.max_decompressed_size = max_decompressed_size;
+        self
+    }
+
+    /// Sets the most points a simple glyph may have.
+    pub fn max_points_per_glyph(mut self, max_points_per_glyph: u16) -> Self {
+        self.options.max_points_per_glyph = max_points_per_glyph;
+        self
+    }
+
+    /// Sets whether to calculate the checksums of the reconstructed font.
+    pub fn compute_checksums(mut self, compute_checksums: bool) -> Self {
+        self.options.compute_checksums = compute_checksums;
+        self
+    }
+
+    /// Sets whether to ignore a wrong `total_compressed_size` in the header.
+    pub fn lenient_compressed_size(mut self, lenient_compressed_size: bool) -> Self {
+        self.options.lenient_compressed_size = lenient_compressed_size;
+        self
+    }
+
+    /// Sets whether to rebuild a `loca` table that was transformed without its `glyf` table.
+    pub fn lenient_loca_transform(mut self, lenient_loca_transform: bool) -> Self {
+        self.options.lenient_loca_transform = lenient_loca_transform;
+        self
+    }
+
+    /// Sets whether to null out the stale `DSIG` table of a single font.
+    pub fn strip_dsig(mut self, strip_dsig: bool) -> Self {
+        self.options.strip_dsig = strip_dsig;
+        self
+    }
+
+    /// Sets whether to write the tables in the order they're stored in the WOFF2 file.
+    pub fn physical_table_order(mut self, physical_table_order: bool) -> Self {
+        self.options.physical_table_order = physical_table_order;
+        self
+    }
+
+    /// Sets the flavor the font must have.
+    pub fn expected_flavor(mut self, expected_flavor: FourCC) -> Self {
+        self.options.expected_flavor = Some(expected_flavor);
+        self
+    }
+
+    /// Sets whether to copy the transformed tables verbatim, which doesn't give a valid font.
+    pub fn skip_glyf_transform(mut self, skip_glyf_transform: bool) -> Self {
+        self.options.skip_glyf_transform = skip_glyf_transform;
+        self
+    }
+
+    /// Sets whether to reject fonts with minor inconsistencies.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.options.strict = strict;
+        self
+    }
+
+    /// Returns the options that have been set.
+    pub fn build(self) -> DecodeOptions {
+        self.options
     }
 }
 
@@ -66,100 +293,2899 @@ pub fn is_woff2(input_buffer: &[u8]) -> bool {
     input_buffer.starts_with(&WOFF2_SIGNATURE.0)
 }
 
-/// Converts a WOFF2 font in `input_buffer` into a TTF format font.
-pub fn convert_woff2_to_ttf(input_buffer: &mut impl Buf) -> Result<Vec<u8>, DecodeError> {
+/// Reads the header and table directory of the WOFF2 font in `input_buffer`, without
+/// decompressing the font data.
+pub fn parse_table_directory(
+    input_buffer: &mut impl Buf,
+) -> Result<Woff2TableDirectory, DecodeError> {
     let header = Woff2Header::from_buf(input_buffer)?;
     header.is_valid_header()?;
+    read_table_directory(input_buffer, &header)
+}
 
-    if !matches!(
-        header.flavor,
-        TTF_COLLECTION_FLAVOR | TTF_CFF_FLAVOR | TTF_TRUE_TYPE_FLAVOR
-    ) {
-        Err(DecodeError::Invalid("Invalid font flavor".to_string()))?;
+/// Reads the table directory following `header`, which mustn't go past the declared file length.
+fn read_table_directory(
+    input_buffer: &mut impl Buf,
+    header: &Woff2Header,
+) -> Result<Woff2TableDirectory, DecodeError> {
+    let declared_length = (header.length as usize).saturating_sub(HEADER_SIZE);
+    Ok(Woff2TableDirectory::from_buf_at(
+        &mut Buf::take(&mut *input_buffer, declared_length),
+        header.num_tables,
+        HEADER_SIZE,
+    )?)
+}
+
+/// Lists the tags of the tables in the WOFF2 font in `input_buffer`, in table directory order.
+pub fn list_tables(input_buffer: &mut impl Buf) -> Result<Vec<FourCC>, DecodeError> {
+    Ok(parse_table_directory(input_buffer)?
+        .tables
+        .iter()
+        .map(|table| table.tag)
+        .collect())
+}
+
+/// Extracts the extended metadata (an XML document) from the WOFF2 font in `input_buffer`.
+///
+/// Returns `None` if the font has no metadata block.
+pub fn extract_metadata(input_buffer: &mut impl Buf) -> Result<Option<Vec<u8>>, DecodeError> {
+    let input_length = input_buffer.remaining();
+    let header = Woff2Header::from_buf(input_buffer)?;
+    header.is_valid_header()?;
+    if header.meta_length == 0 {
+        return Ok(None);
     }
 
-    let table_directory = Woff2TableDirectory::from_buf(input_buffer, header.num_tables)?;
+    let meta_orig_length = header.meta_orig_length as usize;
+    if meta_orig_length > DEFAULT_MAX_DECOMPRESSED_SIZE {
+        Err(DecodeError::TooLarge {
+            size: meta_orig_length,
+            limit: DEFAULT_MAX_DECOMPRESSED_SIZE,
+        })?;
+    }
+    let meta_length = header.meta_length as usize;
+    skip_to_block(
+        input_buffer,
+        input_length,
+        header.meta_offset,
+        meta_length,
+        "Metadata",
+    )?;
 
-    let mut collection_header = if header.flavor == TTF_COLLECTION_FLAVOR {
-        Some(CollectionHeader::from_buf(input_buffer, header.num_tables)?)
+    let mut metadata = Vec::with_capacity(meta_orig_length);
+    DefaultBrotliBackend
+        .decompress(
+            &mut Buf::take(&mut *input_buffer, meta_length).reader(),
+            &mut metadata,
+        )
+        .map_err(DecodeError::Decompression)?;
+    if metadata.len() != meta_orig_length {
+        Err(DecodeError::Invalid("metadata size mismatch".to_string()))?;
+    }
+    Ok(Some(metadata))
+}
+
+/// Extracts the private data block from the WOFF2 font in `input_buffer`.
+///
+/// Unlike the metadata, the private data isn't compressed, so it's returned as it is. Returns
+/// `None` if the font has no private data block.
+pub fn extract_private_data(input_buffer: &mut impl Buf) -> Result<Option<Vec<u8>>, DecodeError> {
+    let input_length = input_buffer.remaining();
+    let header = Woff2Header::from_buf(input_buffer)?;
+    header.is_valid_header()?;
+    if header.private_length == 0 {
+        return Ok(None);
+    }
+
+    let private_length = header.private_length as usize;
+    skip_to_block(
+        input_buffer,
+        input_length,
+        header.private_offset,
+        private_length,
+        "Private data",
+    )?;
+    let mut private_data = vec![0; private_length];
+    input_buffer.copy_to_slice(&mut private_data);
+    Ok(Some(private_data))
+}
+
+/// Advances `input_buffer`, which started out `input_length` bytes long, to the block at `offset`,
+/// checking that all `length` bytes of the block are there.
+fn skip_to_block(
+    input_buffer: &mut impl Buf,
+    input_length: usize,
+    offset: u32,
+    length: usize,
+    block_name: &str,
+) -> Result<(), DecodeError> {
+    let position = input_length - input_buffer.remaining();
+    let skip = (offset as usize)
+        .checked_sub(position)
+        .filter(|&skip| {
+            skip.checked_add(length)
+                .is_some_and(|end| end <= input_buffer.remaining())
+        })
+        .ok_or_else(|| DecodeError::Invalid(format!("{} block out of bounds", block_name)))?;
+    input_buffer.advance(skip);
+    Ok(())
+}
+
+/// Decompresses the WOFF2 font in `input_buffer` and returns just its `(glyf, loca)` tables,
+/// reconstructing them if they were transformed.
+///
+/// For a collection, the first `glyf` table in the table directory is returned. A reconstructed
+/// `loca` table uses long offsets if short ones can't address all of `glyf`, whatever the `head`
+/// table says.
+pub fn decode_glyf_loca(input_buffer: &mut impl Buf) -> Result<(Vec<u8>, Vec<u8>), DecodeError> {
+    let decompressed = decompress_woff2(input_buffer, &DecodeOptions::default())?;
+    find_glyf_loca(&decompressed)
+}
+
+/// Decompresses the WOFF2 font in `input_buffer` and returns the data of the table with the given
+/// `tag` as it's stored, without undoing any transformation (e.g. the transformed `glyf` streams).
+///
+/// Returns `None` if there's no such table. For a collection, the first one in the table directory
+/// is returned.
+pub fn raw_transformed_table(
+    input_buffer: &mut impl Buf,
+    tag: FourCC,
+) -> Result<Option<Vec<u8>>, DecodeError> {
+    let DecompressedWoff2 {
+        table_directory,
+        decompressed_tables,
+        ..
+    } = decompress_woff2(input_buffer, &DecodeOptions::default())?;
+    Ok(table_directory
+        .find(tag)
+        .map(|table| decompressed_tables[table.get_source_range()].to_vec()))
+}
+
+/// Returns the number of glyphs in the WOFF2 font in `input_buffer`.
+///
+/// This is read from the header of a transformed `glyf` table, or else from the `maxp` table (e.g.
+/// for CFF fonts), so the font doesn't have to be reconstructed. For a collection, the first such
+/// table in the table directory is used.
+pub fn glyph_count(input_buffer: &mut impl Buf) -> Result<u16, DecodeError> {
+    let DecompressedWoff2 {
+        table_directory,
+        decompressed_tables,
+        ..
+    } = decompress_woff2(input_buffer, &DecodeOptions::default())?;
+    let tables = &table_directory.tables;
+    // both the transformed `glyf` header and `maxp` have `numGlyphs` at offset 4
+    let table = tables
+        .iter()
+        .find(|table| table.tag == GLYF_TAG && table.transformed)
+        .or_else(|| table_directory.find(MAXP_TAG))
+        .ok_or_else(|| DecodeError::Invalid("Missing `maxp` table".to_string()))?;
+    let num_glyphs = decompressed_tables[table.get_source_range()]
+        .get(4..6)
+        .ok_or_else(|| {
+            DecodeError::Invalid(format!("Truncated `{}` table", display_tag(table.tag)))
+        })?;
+    Ok(u16::from_be_bytes([num_glyphs[0], num_glyphs[1]]))
+}
+
+/// Returns whether each glyph of the WOFF2 font in `input_buffer` has an outline, in glyph index
+/// order.
+///
+/// Partial fonts, like the ones patched together by incremental font transfer, only have outlines
+/// for some of their glyphs and leave the others empty. A transformed `glyf` table is read glyph by
+/// glyph with [`glyphs`], without reconstructing it; otherwise the ranges in `loca` are checked.
+/// For a collection, the first `glyf` table in the table directory is used.
+pub fn glyph_presence(input_buffer: &mut impl Buf) -> Result<Vec<bool>, DecodeError> {
+    let decompressed = decompress_woff2(input_buffer, &DecodeOptions::default())?;
+    let DecompressedWoff2 {
+        table_directory,
+        decompressed_tables,
+        ..
+    } = &decompressed;
+    let glyf = table_directory
+        .find(GLYF_TAG)
+        .ok_or_else(|| DecodeError::Invalid("Missing `glyf` table".to_string()))?;
+    if glyf.transformed {
+        let presence = glyphs(&decompressed_tables[glyf.get_source_range()])
+            .and_then(|glyphs| {
+                glyphs
+                    .map(|glyph| glyph.map(|glyph| !glyph.is_empty()))
+                    .collect()
+            })
+            .map_err(WriteTablesError::from)?;
+        return Ok(presence);
+    }
+    // `indexToLocFormat`
+    let index_format = table_directory
+        .find(HEAD_TAG)
+        .and_then(|head| decompressed_tables[head.get_source_range()].get(50..52))
+        .map(|index_format| u16::from_be_bytes([index_format[0], index_format[1]]))
+        .ok_or_else(|| DecodeError::Invalid("Missing `head` table".to_string()))?;
+    let (glyf, loca) = find_glyf_loca(&decompressed)?;
+    let entry_size = if index_format == 0 { 2 } else { 4 };
+    let num_glyphs = (loca.len() / entry_size).saturating_sub(1);
+    (0..num_glyphs)
+        .map(
+            |glyph_index| match glyph_range(&loca, index_format, glyph_index) {
+                Some(range) if range.end <= glyf.len() => Ok(!range.is_empty()),
+                _ => Err(DecodeError::Invalid(format!(
+                    "Invalid `loca` entry for glyph {}",
+                    glyph_index
+                ))),
+            },
+        )
+        .collect()
+}
+
+/// Decodes the WOFF2 font in `input_buffer` into its tables, in table directory order, without
+/// building an OpenType font out of them.
+///
+/// `glyf`, `loca` and `hmtx` are reconstructed if they were transformed, and `checkSumAdjustment`
+/// in the `head` table is zero. [`finalize_sfnt`](crate::sfnt::finalize_sfnt) turns the result
+/// back into a font. Collections aren't supported, since their fonts share tables.
+pub fn decode_tables(input_buffer: &mut impl Buf) -> Result<Vec<(FourCC, Vec<u8>)>, DecodeError> {
+    let DecompressedWoff2 {
+        table_directory,
+        collection_header,
+        decompressed_tables,
+        ..
+    } = decompress_woff2(input_buffer, &DecodeOptions::default())?;
+    if collection_header.is_some() {
+        return Err(DecodeError::Unsupported(
+            "decoding the tables of a collection",
+        ));
+    }
+    let mut out_buffer = Vec::with_capacity(decompressed_tables.len());
+    let ttf_tables = table_directory.write_tables(
+        &mut out_buffer,
+        &decompressed_tables,
+        true,
+        DEFAULT_MAX_POINTS_PER_GLYPH,
+    )?;
+    Ok(ttf_tables
+        .iter()
+        .map(|record| (record.tag, out_buffer[record.get_range()].to_vec()))
+        .collect())
+}
+
+/// Finds the first `glyf` table and the `loca` table after it, reconstructing them if they were
+/// transformed.
+pub(crate) fn find_glyf_loca(
+    decompressed: &DecompressedWoff2,
+) -> Result<(Vec<u8>, Vec<u8>), DecodeError> {
+    let DecompressedWoff2 {
+        table_directory,
+        decompressed_tables,
+        ..
+    } = decompressed;
+    let mut tables = table_directory.tables.iter();
+    let glyf = tables
+        .find(|table| table.tag == GLYF_TAG)
+        .ok_or_else(|| DecodeError::Invalid("Missing `glyf` table".to_string()))?;
+    let loca = tables
+        .find(|table| table.tag == LOCA_TAG)
+        .ok_or(WriteTablesError::MissingLocaTable)?;
+    if glyf.transformed {
+        let DecodedGlyf { glyf, loca, .. } =
+            decode_glyf_table(&decompressed_tables[glyf.get_source_range()])
+                .map_err(WriteTablesError::from)?;
+        Ok((glyf, loca))
     } else {
-        None
+        Ok((
+            decompressed_tables[glyf.get_source_range()].to_vec(),
+            decompressed_tables[loca.get_source_range()].to_vec(),
+        ))
+    }
+}
+
+/// Converts a WOFF2 font in `input_buffer` into a TTF format font.
+///
+/// `input_buffer` doesn't have to be contiguous, so e.g. a [`bytes::Buf::chain`] of network frames
+/// can be decoded without copying it into one slice first.
+pub fn convert_woff2_to_ttf(input_buffer: &mut impl Buf) -> Result<Vec<u8>, DecodeError> {
+    convert_woff2_to_ttf_with_options(input_buffer, &DecodeOptions::default())
+}
+
+/// Converts a WOFF2 font held in `input` into a TTF format font.
+///
+/// The compressed stream is sliced out of `input` and read by the decompressor in place, so servers
+/// that already hold the font as [`Bytes`] (e.g. a request body) don't have to copy it into a
+/// `Vec` first.
+pub fn convert_woff2_bytes_to_ttf(mut input: Bytes) -> Result<Vec<u8>, DecodeError> {
+    let options = DecodeOptions::default();
+    let mut out_buffer = Vec::new();
+    assemble_font(
+        decompress_woff2_bytes(&mut input, &options)?,
+        &options,
+        &mut out_buffer,
+    )?;
+    Ok(out_buffer)
+}
+
+/// Reads the WOFF2 font at `path` and converts it into a TTF format font.
+#[cfg(feature = "std")]
+pub fn convert_woff2_file_to_ttf(path: impl AsRef<Path>) -> Result<Vec<u8>, DecodeError> {
+    let input = std::fs::read(path).map_err(DecodeError::Io)?;
+    convert_woff2_to_ttf(&mut input.as_slice())
+}
+
+/// Converts a WOFF2 font in `input_buffer` into a TTF format font, writing it to `out_buffer`.
+///
+/// `out_buffer` is cleared first, so one buffer can be reused for many fonts without allocating
+/// each time. If decoding fails, `out_buffer` is left empty, but keeps its allocation.
+pub fn convert_woff2_to_ttf_into(
+    input_buffer: &mut impl Buf,
+    out_buffer: &mut Vec<u8>,
+) -> Result<(), DecodeError> {
+    convert_woff2(input_buffer, &DecodeOptions::default(), out_buffer)
+}
+
+/// Converts a WOFF2 font in `input_buffer` into a TTF format font, using the given options.
+pub fn convert_woff2_to_ttf_with_options(
+    input_buffer: &mut impl Buf,
+    options: &DecodeOptions,
+) -> Result<Vec<u8>, DecodeError> {
+    convert_woff2(input_buffer, options, Vec::new())
+}
+
+/// Somewhere to put a decoded font, for [`convert_woff2`].
+///
+/// The decoder goes back to fill in the headers once all of the tables are written, so the font is
+/// assembled in a buffer and handed over once it's complete.
+pub trait SfntSink {
+    /// What [`convert_woff2`] returns
+    type Output;
+
+    /// Returns the buffer to assemble the font in. It's cleared first, and left empty if decoding
+    /// fails; a sink that outlives the call, like `&mut Vec<u8>`, keeps the buffer's allocation.
+    fn buffer(&mut self) -> &mut Vec<u8>;
+
+    /// Takes the decoded font, which is in [`buffer`](Self::buffer).
+    fn finish(self) -> Result<Self::Output, DecodeError>;
+}
+
+/// Assembles the font in the `Vec` itself, reusing its allocation, and returns it.
+impl SfntSink for Vec<u8> {
+    type Output = Vec<u8>;
+
+    fn buffer(&mut self) -> &mut Vec<u8> {
+        self
+    }
+
+    fn finish(self) -> Result<Vec<u8>, DecodeError> {
+        Ok(self)
+    }
+}
+
+/// Assembles the font in the borrowed `Vec`, replacing what was in it.
+impl SfntSink for &mut Vec<u8> {
+    type Output = ();
+
+    fn buffer(&mut self) -> &mut Vec<u8> {
+        self
+    }
+
+    fn finish(self) -> Result<(), DecodeError> {
+        Ok(())
+    }
+}
+
+/// Writes the font to a writer, once it's complete.
+///
+/// A `&mut Vec<u8>` is a sink of its own that replaces its contents, so writers are wrapped in this
+/// instead of being sinks themselves. Wrapping a `Vec` appends the font to it.
+#[derive(Debug)]
+pub struct WriteSink<W> {
+    writer: W,
+    font: Vec<u8>,
+}
+
+impl<W: Write> WriteSink<W> {
+    /// Creates a sink that writes the decoded font to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            font: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> SfntSink for WriteSink<W> {
+    type Output = ();
+
+    fn buffer(&mut self) -> &mut Vec<u8> {
+        &mut self.font
+    }
+
+    fn finish(mut self) -> Result<(), DecodeError> {
+        self.writer
+            .write_all(&self.font)
+            .map_err(DecodeError::Write)
+    }
+}
+
+/// Converts a WOFF2 font in `input_buffer` into a TTF format font, using the given options, and
+/// hands it to `sink`.
+///
+/// The other `convert_woff2_to_ttf*` functions that return just the font are wrappers around this.
+pub fn convert_woff2<S: SfntSink>(
+    input_buffer: &mut impl Buf,
+    options: &DecodeOptions,
+    mut sink: S,
+) -> Result<S::Output, DecodeError> {
+    let font = sink.buffer();
+    if let Err(error) = decode_font_into(input_buffer, options, font) {
+        // don't leave the previous contents or a partial font behind
+        font.clear();
+        return Err(error);
+    }
+    sink.finish()
+}
+
+/// Converts a WOFF2 font in `input_buffer` into a TTF format font, also returning the tags of the
+/// tables in the order they appear in the WOFF2 table directory.
+///
+/// The table directory of the output font is always sorted by tag, as required by OpenType, so this
+/// is the only way to recover the original order (e.g. for diffing tools).
+pub fn convert_woff2_to_ttf_with_table_order(
+    input_buffer: &mut impl Buf,
+    options: &DecodeOptions,
+) -> Result<(Vec<u8>, Vec<FourCC>), DecodeError> {
+    decode_font(input_buffer, options).map(|(ttf, table_order, _)| (ttf, table_order))
+}
+
+/// Converts a WOFF2 font in `input_buffer` into a TTF format font, also returning what kind of font
+/// it is according to the WOFF2 headers.
+///
+/// This saves parsing the output again just to find out whether it's a collection.
+pub fn convert_woff2_to_ttf_with_header(
+    input_buffer: &mut impl Buf,
+    options: &DecodeOptions,
+) -> Result<(Vec<u8>, FontInfo), DecodeError> {
+    decode_font(input_buffer, options).map(|(ttf, _, info)| (ttf, info))
+}
+
+/// What kind of font a WOFF2 file contains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FontInfo {
+    /// The sfnt version of the font, or `ttcf` for a collection
+    pub flavor: FourCC,
+    /// The number of fonts, which is 1 unless the font is a collection
+    pub num_fonts: usize,
+    /// The major version of the WOFF2 file, as given by its producer
+    ///
+    /// This and `minor_version` are informational only; they aren't written to the decoded font,
+    /// whose own version is in the `head` table.
+    pub major_version: u16,
+    /// The minor version of the WOFF2 file, as given by its producer
+    pub minor_version: u16,
+}
+
+impl FontInfo {
+    pub fn is_collection(&self) -> bool {
+        self.flavor == TTF_COLLECTION_FLAVOR
+    }
+}
+
+/// Converts a WOFF2 font in `input_buffer` into a TTF format font, decompressing and writing one
+/// table at a time.
+///
+/// This saves holding all of the decompressed table data in memory alongside the output, which
+/// matters for very large fonts. Only transformed tables (usually `glyf` and `hmtx`) are buffered
+/// before being written. On the other hand, the brotli decompressor's window is kept until the
+/// end, so small fonts take more memory this way. The tables come out in the same order as with
+/// [`convert_woff2_to_ttf_with_options`], unless a `loca` table doesn't directly follow its `glyf`
+/// table.
+///
+/// The memory savings depend on [`BrotliBackend::decompress_reader`], which decompresses
+/// everything up front unless the backend overrides it (as the default backend does).
+#[cfg(feature = "streaming")]
+pub fn convert_woff2_to_ttf_streaming(
+    input_buffer: &mut impl Buf,
+    options: &DecodeOptions,
+) -> Result<Vec<u8>, DecodeError> {
+    let Woff2Headers {
+        header,
+        mut table_directory,
+        collection_header,
+        input_length,
+        compressed_size,
+    } = read_woff2_headers(input_buffer, options)?;
+    if options.skip_glyf_transform {
+        keep_transformed_tables(&mut table_directory);
+    }
+
+    let mut out_buffer = Vec::new();
+    prepare_output(
+        &mut out_buffer,
+        &header,
+        &table_directory,
+        &collection_header,
+    );
+    let mut compressed_stream =
+        CompressedStream::new(Buf::take(&mut *input_buffer, compressed_size));
+    let mut ttf_tables = {
+        let mut decompressor = options
+            .brotli_backend
+            .decompress_reader(&mut compressed_stream)
+            .map_err(DecodeError::Decompression)?;
+        let ttf_tables = table_directory.write_tables_streaming(
+            &mut out_buffer,
+            &mut decompressor,
+            options.max_points_per_glyph,
+        )?;
+        // the tables should use up all of the decompressed data
+        if decompressor
+            .read(&mut [0])
+            .map_err(DecodeError::Decompression)?
+            != 0
+        {
+            Err(DecodeError::Invalid(
+                "decompressed size mismatch".to_string(),
+            ))?;
+        }
+        ttf_tables
     };
+    compressed_stream.finish()?;
+    check_stream_padding(input_buffer, input_length, &header)?;
+    if !options.compute_checksums {
+        // they're calculated along the way, but the output should match the regular decoder's
+        for table in &mut ttf_tables {
+            table.checksum = 0;
+        }
+    }
 
-    // for checking the compressed size
-    let stream_start_remaining = input_buffer.remaining();
+    finish_font(
+        &header,
+        &table_directory,
+        collection_header,
+        &mut out_buffer,
+        ttf_tables,
+        options,
+    )?;
+    Ok(out_buffer)
+}
 
-    let mut decompressed_tables =
-        Vec::with_capacity(table_directory.uncompressed_length.try_into().unwrap());
+fn decode_font(
+    input_buffer: &mut impl Buf,
+    options: &DecodeOptions,
+) -> Result<(Vec<u8>, Vec<FourCC>, FontInfo), DecodeError> {
+    let mut out_buffer = Vec::new();
+    let (table_order, info) = decode_font_into(input_buffer, options, &mut out_buffer)?;
+    Ok((out_buffer, table_order, info))
+}
 
-    brotli::BrotliDecompress(&mut input_buffer.reader(), &mut decompressed_tables)?;
+fn decode_font_into(
+    input_buffer: &mut impl Buf,
+    options: &DecodeOptions,
+    out_buffer: &mut Vec<u8>,
+) -> Result<(Vec<FourCC>, FontInfo), DecodeError> {
+    assemble_font(
+        decompress_woff2(input_buffer, options)?,
+        options,
+        out_buffer,
+    )
+}
 
-    let compressed_size = stream_start_remaining - input_buffer.remaining();
+/// Builds the font out of the decompressed tables in `out_buffer`.
+fn assemble_font(
+    decompressed: DecompressedWoff2,
+    options: &DecodeOptions,
+    out_buffer: &mut Vec<u8>,
+) -> Result<(Vec<FourCC>, FontInfo), DecodeError> {
+    let DecompressedWoff2 {
+        header,
+        table_directory,
+        collection_header,
+        decompressed_tables,
+        ..
+    } = decompressed;
+    let table_order = table_directory
+        .tables
+        .iter()
+        .map(|table| table.tag)
+        .collect();
+    let info = FontInfo {
+        flavor: header.flavor,
+        num_fonts: collection_header
+            .as_ref()
+            .map_or(1, |collection_header| collection_header.fonts.len()),
+        major_version: header.major_version,
+        minor_version: header.minor_version,
+    };
 
-    if compressed_size != usize::try_from(header.total_compressed_size).unwrap() + 1 {
-        Err(DecodeError::Invalid(
-            "Compressed stream size does not match header".to_string(),
-        ))?;
+    prepare_output(out_buffer, &header, &table_directory, &collection_header);
+    let mut ttf_tables = table_directory.write_tables(
+        out_buffer,
+        &decompressed_tables,
+        options.physical_table_order,
+        options.max_points_per_glyph,
+    )?;
+    if options.compute_checksums {
+        calculate_table_checksums(out_buffer, &mut ttf_tables);
     }
+    finish_font(
+        &header,
+        &table_directory,
+        collection_header,
+        out_buffer,
+        ttf_tables,
+        options,
+    )?;
+
+    Ok((table_order, info))
+}
 
-    let mut out_buffer = Vec::with_capacity(header.total_sfnt_size as usize);
+/// Clears `out_buffer` and makes space for the headers at the start.
+fn prepare_output(
+    out_buffer: &mut Vec<u8>,
+    header: &Woff2Header,
+    table_directory: &Woff2TableDirectory,
+    collection_header: &Option<CollectionHeader>,
+) {
+    out_buffer.clear();
+    out_buffer.reserve(header.total_sfnt_size as usize);
     // space for headers; we'll fill this in later once we've calculated table locations and
     // checksums
-    let header_end = if let Some(collection_header) = &collection_header {
+    let header_end = if let Some(collection_header) = collection_header {
         collection_header.calculate_header_size()
     } else {
         calculate_header_size(table_directory.tables.len())
     };
     out_buffer.resize(header_end, 0);
-    let ttf_tables = table_directory.write_to_buf(&mut out_buffer, &decompressed_tables)?;
+}
+
+/// Checks the tables that have been written to `out_buffer`, then fills in the headers.
+fn finish_font(
+    header: &Woff2Header,
+    table_directory: &Woff2TableDirectory,
+    collection_header: Option<CollectionHeader>,
+    out_buffer: &mut [u8],
+    mut ttf_tables: Vec<TableRecord>,
+    options: &DecodeOptions,
+) -> Result<(), DecodeError> {
+    if options.strip_dsig && collection_header.is_none() {
+        null_dsig_table(out_buffer, &mut ttf_tables, options.compute_checksums);
+    }
+    if options.strict {
+        check_sfnt_size(header, table_directory, &ttf_tables, out_buffer.len())?;
+        if collection_header.is_none() && !ttf_tables.iter().any(|table| table.tag == CMAP_TAG) {
+            return Err(DecodeError::Invalid("Missing `cmap` table".into()));
+        }
+    }
 
-    let mut header_buffer = &mut out_buffer[..header_end];
-    if let Some(collection_header) = &mut collection_header {
+    if let Some(mut collection_header) = collection_header {
         // sort tables for each font
         for font in &mut collection_header.fonts {
             font.table_indices
                 .sort_unstable_by_key(|&idx| ttf_tables[idx as usize].tag.0);
         }
-        collection_header.write_to_buf(&mut header_buffer, &ttf_tables);
+        let header_end = collection_header.calculate_header_size();
+        collection_header.write_to_buf(&mut &mut out_buffer[..header_end], &ttf_tables);
     } else {
-        let ttf_header = TableDirectory::new(header.flavor, ttf_tables);
-        ttf_header.write_to_buf(&mut header_buffer);
-        // calculate font checksum and store it at the appropriate location
-        let head_table_record = ttf_header
-            .find_table(HEAD_TAG)
-            .ok_or_else(|| DecodeError::Invalid("Missing `head` table".into()))?;
-        let checksum_adjustment = calculate_font_checksum_adjustment(&out_buffer);
-        let head_table = &mut out_buffer[head_table_record.get_range()];
-        set_checksum_adjustment(head_table, checksum_adjustment)?;
+        let has_head = if options.compute_checksums {
+            write_table_directory(out_buffer, header.flavor, ttf_tables)?
+        } else {
+            let table_directory = TableDirectory::new(header.flavor, ttf_tables);
+            table_directory.write_to_buf(&mut &mut out_buffer[..]);
+            table_directory.find_table(HEAD_TAG).is_some()
+        };
+        if !has_head && options.strict {
+            return Err(DecodeError::Invalid("Missing `head` table".into()));
+        }
     }
+    Ok(())
+}
 
-    Ok(out_buffer)
+/// The parts of a WOFF2 file needed to reconstruct the font.
+pub(crate) struct DecompressedWoff2 {
+    pub header: Woff2Header,
+    pub table_directory: Woff2TableDirectory,
+    pub collection_header: Option<CollectionHeader>,
+    pub decompressed_tables: Vec<u8>,
+    /// The length of the brotli stream the tables were decompressed from, as read by the
+    /// decompressor
+    pub compressed_size: usize,
 }
 
-#[cfg(test)]
-mod tests {
-    use std::io::Cursor;
+/// The headers of a WOFF2 file, up to the compressed stream.
+struct Woff2Headers {
+    header: Woff2Header,
+    table_directory: Woff2TableDirectory,
+    collection_header: Option<CollectionHeader>,
+    /// The length of the whole input, for locating blocks by their offset in the file
+    input_length: usize,
+    /// The length of the compressed stream
+    compressed_size: usize,
+}
 
-    use crate::test_resources::{FONTAWESOME_REGULAR_400, LATO_V22_LATIN_REGULAR};
+/// Reads the headers of the WOFF2 font in `input_buffer`, leaving it at the start of the compressed
+/// stream.
+fn read_woff2_headers(
+    input_buffer: &mut impl Buf,
+    options: &DecodeOptions,
+) -> Result<Woff2Headers, DecodeError> {
+    let input_length = input_buffer.remaining();
+    let header = Woff2Header::from_buf(input_buffer)?;
+    header.is_valid_header()?;
 
-    use super::convert_woff2_to_ttf;
+    if !matches!(
+        header.flavor,
+        TTF_COLLECTION_FLAVOR | TTF_CFF_FLAVOR | TTF_TRUE_TYPE_FLAVOR
+    ) {
+        Err(DecodeError::Invalid("Invalid font flavor".to_string()))?;
+    }
+    if let Some(expected) = options.expected_flavor {
+        if header.flavor != expected {
+            Err(DecodeError::BadFlavor {
+                flavor: header.flavor,
+                expected,
+            })?;
+        }
+    }
+    debug!(
+        "WOFF2 header: flavor `{}`, {} tables, {} bytes",
+        display_tag(header.flavor),
+        header.num_tables,
+        header.length
+    );
 
-    #[test]
-    fn read_sample_font() {
-        let buffer = LATO_V22_LATIN_REGULAR;
-        let ttf = convert_woff2_to_ttf(&mut Cursor::new(buffer)).unwrap();
-        assert_eq!(None, ttf_parser::fonts_in_collection(&ttf));
-        let _parsed_ttf = ttf_parser::Face::from_slice(&ttf, 1).unwrap();
+    let table_directory = read_table_directory(input_buffer, &header)?;
+    debug!(
+        "Table directory: {} tables, {} bytes decompressed",
+        table_directory.tables.len(),
+        table_directory.uncompressed_length
+    );
+    let collection_header = if header.flavor == TTF_COLLECTION_FLAVOR {
+        Some(CollectionHeader::from_buf(input_buffer, header.num_tables)?)
+    } else {
+        None
+    };
+    // a font with two tables with the same tag isn't valid
+    match &collection_header {
+        Some(collection_header) => {
+            for font in &collection_header.fonts {
+                table_directory
+                    .check_unique_tags(font.table_indices.iter().map(|&index| index as usize))?;
+            }
+        }
+        None => table_directory.check_unique_tags(0..table_directory.tables.len())?,
     }
-    #[test]
-    // Spec: https://www.w3.org/TR/WOFF2/#table_order
-    // The loca table MUST follow the glyf table in the table directory. When WOFF2 file contains individually encoded font file, the table directory MAY contain other tables inserted between glyf and loca tables; however when WOFF2 contains a font collection file each loca table MUST immediately follow its corresponding glyf table. For example, the following order of tables: 'cmap', 'glyf', 'hhea', 'hmtx', 'loca', 'maxp' ... is acceptable for individually encoded font files;
-    fn read_loca_is_not_after_glyf_font() {
-        // In this test font, the loca table does not follow the glyf table.
-        let buffer = FONTAWESOME_REGULAR_400;
-        let ttf = convert_woff2_to_ttf(&mut Cursor::new(buffer)).unwrap();
-        assert_eq!(None, ttf_parser::fonts_in_collection(&ttf));
-        let _parsed_ttf = ttf_parser::Face::from_slice(&ttf, 1).unwrap();
+
+    let compressed_size = if options.lenient_compressed_size {
+        // the brotli stream marks its own end, so hand the decompressor everything up to the next
+        // block and let it stop where it stops
+        let stream_start = input_length - input_buffer.remaining();
+        next_block_offset(&header)
+            .saturating_sub(stream_start)
+            .min(input_buffer.remaining())
+    } else {
+        usize::try_from(header.total_compressed_size).unwrap()
+    };
+    if compressed_size != header.total_compressed_size as usize {
+        warn!(
+            "Compressed stream is {} bytes, but the header says {}",
+            compressed_size, header.total_compressed_size
+        );
+    }
+    if input_buffer.remaining() < compressed_size {
+        Err(DecodeError::Invalid(
+            "Compressed stream size does not match header".to_string(),
+        ))?;
+    }
+
+    let uncompressed_length = usize::try_from(table_directory.uncompressed_length).unwrap();
+    if uncompressed_length > options.max_decompressed_size {
+        Err(DecodeError::TooLarge {
+            size: uncompressed_length,
+            limit: options.max_decompressed_size,
+        })?;
+    }
+
+    Ok(Woff2Headers {
+        header,
+        table_directory,
+        collection_header,
+        input_length,
+        compressed_size,
+    })
+}
+
+/// Reads the headers of the WOFF2 font in `input_buffer` and decompresses the table data.
+pub(crate) fn decompress_woff2(
+    input_buffer: &mut impl Buf,
+    options: &DecodeOptions,
+) -> Result<DecompressedWoff2, DecodeError> {
+    let headers = read_woff2_headers(input_buffer, options)?;
+    // limit the decompressor to the compressed stream so that it can't read into the metadata or
+    // private data blocks
+    let mut compressed_stream =
+        CompressedStream::new(Buf::take(&mut *input_buffer, headers.compressed_size));
+    let decompressed_tables = decompress_tables(&headers, &mut compressed_stream, options)?;
+    let compressed_size = compressed_stream.finish()?;
+    check_decompressed_tables(
+        input_buffer,
+        headers,
+        decompressed_tables,
+        compressed_size,
+        options,
+    )
+}
+
+/// Like [`decompress_woff2`], but the decompressor reads the compressed stream straight out of
+/// `input`'s memory.
+fn decompress_woff2_bytes(
+    input: &mut Bytes,
+    options: &DecodeOptions,
+) -> Result<DecompressedWoff2, DecodeError> {
+    let (headers, compressed_stream) = split_compressed_stream(input, options)?;
+    let mut compressed_stream = CompressedStream::new(compressed_stream);
+    let decompressed_tables = decompress_tables(&headers, &mut compressed_stream, options)?;
+    let compressed_size = compressed_stream.finish()?;
+    check_decompressed_tables(
+        input,
+        headers,
+        decompressed_tables,
+        compressed_size,
+        options,
+    )
+}
+
+/// Reads the headers of the WOFF2 font in `input`, then splits the compressed stream off the front
+/// of what's left. The stream is a view of `input`, not a copy.
+fn split_compressed_stream(
+    input: &mut Bytes,
+    options: &DecodeOptions,
+) -> Result<(Woff2Headers, Bytes), DecodeError> {
+    let headers = read_woff2_headers(input, options)?;
+    // `read_woff2_headers` checked that the stream fits
+    let compressed_stream = input.split_to(headers.compressed_size);
+    Ok((headers, compressed_stream))
+}
+
+/// The most zero padding allowed at the end of the compressed stream, within its declared size
+const MAX_STREAM_PADDING: usize = 3;
+
+/// The compressed stream, as read by the brotli backend.
+///
+/// Decompressors read ahead into a buffer, so they can't say where the brotli stream ended. The
+/// last few bytes are handed over one at a time instead: if the brotli stream ends among them,
+/// exactly the bytes after it are left unread, and if it ends any earlier, more than
+/// [`MAX_STREAM_PADDING`] bytes are.
+struct CompressedStream<B> {
+    stream: B,
+    length: usize,
+}
+
+impl<B: Buf> CompressedStream<B> {
+    fn new(stream: B) -> Self {
+        let length = stream.remaining();
+        Self { stream, length }
+    }
+
+    /// Checks that the brotli stream used up the compressed stream, apart from some zero padding,
+    /// and returns the length of the brotli stream.
+    fn finish(mut self) -> Result<usize, DecodeError> {
+        let unused = self.stream.remaining();
+        if unused > MAX_STREAM_PADDING || (0..unused).any(|_| self.stream.get_u8() != 0) {
+            Err(DecodeError::Invalid(
+                "Unexpected data after compressed stream".to_string(),
+            ))?;
+        }
+        Ok(self.length - unused)
+    }
+}
+
+impl<B: Buf> Read for CompressedStream<B> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = self.stream.remaining();
+        let available = match remaining.checked_sub(MAX_STREAM_PADDING + 1) {
+            Some(bulk) if bulk > 0 => bulk,
+            _ => remaining.min(1),
+        };
+        let length = available.min(buf.len()).min(self.stream.chunk().len());
+        self.stream.copy_to_slice(&mut buf[..length]);
+        Ok(length)
+    }
+}
+
+/// Collects the decompressed tables, refusing to take more than the table directory says there is.
+struct DecompressedTables {
+    data: Vec<u8>,
+    length: usize,
+    overflowed: bool,
+}
+
+impl Write for DecompressedTables {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.len() > self.length - self.data.len() {
+            self.overflowed = true;
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "decompressed size mismatch",
+            ));
+        }
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn decompress_tables(
+    headers: &Woff2Headers,
+    compressed_stream: &mut dyn Read,
+    options: &DecodeOptions,
+) -> Result<Vec<u8>, DecodeError> {
+    let uncompressed_length = usize::try_from(headers.table_directory.uncompressed_length).unwrap();
+    let mut decompressed_tables = DecompressedTables {
+        data: Vec::with_capacity(uncompressed_length),
+        length: uncompressed_length,
+        overflowed: false,
+    };
+    match options
+        .brotli_backend
+        .decompress(compressed_stream, &mut decompressed_tables)
+    {
+        Ok(()) => Ok(decompressed_tables.data),
+        // stopped as soon as there was too much data, rather than decompressing all of it
+        Err(_) if decompressed_tables.overflowed => Err(DecodeError::Invalid(
+            "decompressed size mismatch".to_string(),
+        )),
+        Err(error) => Err(DecodeError::Decompression(error)),
+    }
+}
+
+/// Checks the decompressed tables and the padding after the compressed stream, which
+/// `input_buffer` is left at the start of.
+fn check_decompressed_tables(
+    input_buffer: &mut impl Buf,
+    headers: Woff2Headers,
+    mut decompressed_tables: Vec<u8>,
+    compressed_size: usize,
+    options: &DecodeOptions,
+) -> Result<DecompressedWoff2, DecodeError> {
+    let Woff2Headers {
+        header,
+        mut table_directory,
+        collection_header,
+        input_length,
+        ..
+    } = headers;
+    check_stream_padding(input_buffer, input_length, &header)?;
+
+    let uncompressed_length = usize::try_from(table_directory.uncompressed_length).unwrap();
+    if decompressed_tables.len() != uncompressed_length {
+        Err(DecodeError::Invalid(
+            "decompressed size mismatch".to_string(),
+        ))?;
+    }
+    match &collection_header {
+        Some(collection_header) => {
+            for font in &collection_header.fonts {
+                check_glyf_header(
+                    &table_directory,
+                    font.table_indices.iter().map(|&index| index as usize),
+                    &decompressed_tables,
+                    options.strict,
+                )?;
+            }
+        }
+        None => check_glyf_header(
+            &table_directory,
+            0..table_directory.tables.len(),
+            &decompressed_tables,
+            options.strict,
+        )?,
+    }
+    if options.skip_glyf_transform {
+        keep_transformed_tables(&mut table_directory);
+    } else if options.lenient_loca_transform && !options.strict && collection_header.is_none() {
+        rebuild_lone_loca(&mut table_directory, &mut decompressed_tables);
+    }
+
+    Ok(DecompressedWoff2 {
+        header,
+        table_directory,
+        collection_header,
+        decompressed_tables,
+        compressed_size,
+    })
+}
+
+/// Checks the header of the transformed `glyf` table against the other tables at `indices`:
+/// `numGlyphs` against the `maxp` table, and in strict mode `indexFormat` against
+/// `indexToLocFormat` in the `head` table.
+///
+/// Both `numGlyphs` are at offset 4. Outside of strict mode, a mismatched `head` table is fixed up
+/// when the tables are written instead, trusting the `glyf` header. Fonts without a transformed
+/// `glyf` table, or without the table to compare it with, aren't checked.
+fn check_glyf_header(
+    table_directory: &Woff2TableDirectory,
+    indices: impl Iterator<Item = usize>,
+    decompressed_tables: &[u8],
+    strict: bool,
+) -> Result<(), DecodeError> {
+    let mut glyf = None;
+    let mut maxp = None;
+    let mut head = None;
+    for table in indices.map(|index| &table_directory.tables[index]) {
+        match table.tag {
+            GLYF_TAG if table.transformed => glyf = Some(table),
+            MAXP_TAG => maxp = Some(table),
+            HEAD_TAG => head = Some(table),
+            _ => {}
+        }
+    }
+    let read_u16 = |table: Option<&TableDirectoryEntry>, offset: usize| {
+        decompressed_tables[table?.get_source_range()]
+            .get(offset..offset + 2)
+            .map(|value| u16::from_be_bytes([value[0], value[1]]))
+    };
+    match (read_u16(glyf, 4), read_u16(maxp, 4)) {
+        (Some(glyf_num_glyphs), Some(maxp_num_glyphs)) if glyf_num_glyphs != maxp_num_glyphs => {
+            return Err(DecodeError::Invalid(
+                "maxp/glyf glyph count mismatch".to_string(),
+            ));
+        }
+        _ => {}
+    }
+    match (read_u16(glyf, 6), read_u16(head, 50)) {
+        (Some(index_format), Some(head_index_format))
+            if strict && index_format != head_index_format =>
+        {
+            Err(DecodeError::Invalid(
+                "indexToLocFormat mismatch".to_string(),
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks the size of the reconstructed font against the `total_sfnt_size` in the header.
+///
+/// Reconstructed `glyf` and `loca` tables don't have to match the originals byte for byte, so their
+/// original lengths from the table directory are counted instead. All tables are padded to a
+/// multiple of four bytes.
+fn check_sfnt_size(
+    header: &Woff2Header,
+    table_directory: &Woff2TableDirectory,
+    ttf_tables: &[TableRecord],
+    output_length: usize,
+) -> Result<(), DecodeError> {
+    let padded = |length: u32| (length as usize + 3) & !3;
+    let reconstructed_length: usize = ttf_tables
+        .iter()
+        .filter(|table| matches!(table.tag, GLYF_TAG | LOCA_TAG))
+        .map(|table| padded(table.length))
+        .sum();
+    let original_length: usize = table_directory
+        .tables
+        .iter()
+        .filter(|table| matches!(table.tag, GLYF_TAG | LOCA_TAG))
+        .map(|table| padded(table.dest_length))
+        .sum();
+    if output_length - reconstructed_length + original_length != header.total_sfnt_size as usize {
+        Err(DecodeError::Invalid("sfnt size mismatch".to_string()))?;
+    }
+    Ok(())
+}
+
+/// Marks the transformed tables as untransformed, so that their data is copied to the output as it
+/// is, for [`DecodeOptions::skip_glyf_transform`].
+fn keep_transformed_tables(table_directory: &mut Woff2TableDirectory) {
+    for table in &mut table_directory.tables {
+        if table.transformed {
+            debug!(
+                "Copying transformed `{}` ({} bytes) without reconstructing it",
+                display_tag(table.tag),
+                table.src_length
+            );
+            table.transformed = false;
+        }
+    }
+}
+
+/// Rebuilds a transformed `loca` table whose `glyf` table isn't transformed. The rebuilt table is
+/// appended to the decompressed tables, and its directory entry is pointed there as an
+/// untransformed table.
+///
+/// If it can't be rebuilt, the font is left alone so that decoding fails as usual.
+fn rebuild_lone_loca(table_directory: &mut Woff2TableDirectory, decompressed_tables: &mut Vec<u8>) {
+    let (Some(glyf), Some(loca_index), Some(head)) = (
+        table_directory.find(GLYF_TAG),
+        table_directory
+            .tables
+            .iter()
+            .position(|table| table.tag == LOCA_TAG),
+        table_directory.find(HEAD_TAG),
+    ) else {
+        return;
+    };
+    let loca = &table_directory.tables[loca_index];
+    if glyf.transformed || !loca.transformed {
+        return;
+    }
+    // `indexToLocFormat`
+    let Some(index_format) = decompressed_tables[head.get_source_range()].get(50..52) else {
+        return;
+    };
+    let index_format = u16::from_be_bytes([index_format[0], index_format[1]]);
+    let Some(rebuilt) = rebuild_loca(
+        &decompressed_tables[glyf.get_source_range()],
+        index_format,
+        loca.dest_length as usize,
+    ) else {
+        return;
+    };
+    let loca = &mut table_directory.tables[loca_index];
+    loca.transformed = false;
+    loca.src_offset = decompressed_tables.len() as u32;
+    loca.src_length = rebuilt.len() as u32;
+    decompressed_tables.extend_from_slice(&rebuilt);
+    warn!("Rebuilt a `loca` table that was transformed without its `glyf` table");
+}
+
+/// Replaces the `DSIG` table in `font`, if any, with an empty signature (version 1, no signatures,
+/// no flags) padded with zeros to its original length, and updates its checksum.
+fn null_dsig_table(font: &mut [u8], tables: &mut [TableRecord], compute_checksum: bool) {
+    let Some(table) = tables.iter_mut().find(|table| table.tag == DSIG_TAG) else {
+        return;
+    };
+    let Some(data) = font.get_mut(table.get_range()) else {
+        return;
+    };
+    debug!("Nulling out the `DSIG` table");
+    data.fill(0);
+    if let Some(version) = data.get_mut(..4) {
+        version.copy_from_slice(&1u32.to_be_bytes());
+    }
+    if compute_checksum {
+        table.checksum = calculate_checksum(data);
+    }
+}
+
+/// Returns the offset of the block after the compressed stream: the metadata block, the private
+/// data block, or the end of the file.
+///
+/// The private data block should come after the metadata block, but whichever comes first is taken.
+fn next_block_offset(header: &Woff2Header) -> usize {
+    let meta_offset = (header.meta_length != 0).then_some(header.meta_offset);
+    let private_offset = (header.private_length != 0).then_some(header.private_offset);
+    meta_offset
+        .into_iter()
+        .chain(private_offset)
+        .min()
+        .unwrap_or(header.length) as usize
+}
+
+/// Checks that the compressed stream is followed only by zero padding up to the next block (the
+/// metadata block, the private data block, or the end of the file).
+fn check_stream_padding(
+    input_buffer: &mut impl Buf,
+    input_length: usize,
+    header: &Woff2Header,
+) -> Result<(), DecodeError> {
+    let stream_end = input_length - input_buffer.remaining();
+    let padding_length = next_block_offset(header)
+        .checked_sub(stream_end)
+        .ok_or_else(|| DecodeError::Invalid("Compressed stream overlaps next block".to_string()))?;
+    // blocks are 4-byte aligned, so there are at most 3 bytes of padding
+    if padding_length > 3 {
+        Err(DecodeError::Invalid(
+            "Unexpected data after compressed stream".to_string(),
+        ))?;
+    }
+    for _ in 0..padding_length.min(input_buffer.remaining()) {
+        if input_buffer.get_u8() != 0 {
+            Err(DecodeError::Invalid(
+                "Unexpected data after compressed stream".to_string(),
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::test_resources::{
+        bitmap_font_tables, stored_brotli_stream, variable_font_tables,
+        woff2_collection_with_tables, woff2_with_table_data, woff2_with_tables,
+        woff2_with_transformed_loca, FONTAWESOME_REGULAR_400, LATO_V22_LATIN_REGULAR,
+        LATO_V22_LATIN_REGULAR_HMTX_IDENTITY, LATO_V22_LATIN_REGULAR_HMTX_TRANSFORMED,
+        TEST_KERN_ONE_CFF,
+    };
+
+    use super::{
+        convert_woff2_to_ttf, convert_woff2_to_ttf_with_header, convert_woff2_to_ttf_with_options,
+        convert_woff2_to_ttf_with_table_order, decode_glyf_loca, DecodeError, DecodeOptions,
+        TableDirectoryError, Woff2Header, Woff2TableDirectory,
+    };
+
+    #[test]
+    fn read_sample_font() {
+        let buffer = LATO_V22_LATIN_REGULAR;
+        let ttf = convert_woff2_to_ttf(&mut Cursor::new(buffer)).unwrap();
+        assert_eq!(None, ttf_parser::fonts_in_collection(&ttf));
+        let _parsed_ttf = ttf_parser::Face::from_slice(&ttf, 1).unwrap();
+    }
+
+    #[test]
+    fn post_format_2_glyph_names() {
+        let ttf = convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        let face = ttf_parser::Face::from_slice(&ttf, 0).unwrap();
+        let post = ttf_parser::RawFace::from_slice(&ttf, 0)
+            .unwrap()
+            .table(ttf_parser::Tag::from_bytes(b"post"))
+            .unwrap();
+        assert_eq!(post[..4], [0, 2, 0, 0]);
+        // the table has an odd length, so it's padded in the font
+        assert_eq!(post.len() % 4, 1);
+
+        // the names after the standard Macintosh ones are length-prefixed strings at the end
+        let num_glyphs = u16::from_be_bytes([post[32], post[33]]);
+        let mut names = Vec::new();
+        let mut strings = &post[34 + 2 * num_glyphs as usize..];
+        while let Some((&length, rest)) = strings.split_first() {
+            let (name, rest) = rest.split_at(length as usize);
+            names.push(std::str::from_utf8(name).unwrap());
+            strings = rest;
+        }
+        for glyph_index in 0..num_glyphs {
+            let offset = 34 + 2 * glyph_index as usize;
+            let name_index = u16::from_be_bytes([post[offset], post[offset + 1]]) as usize;
+            let name = face.glyph_name(ttf_parser::GlyphId(glyph_index)).unwrap();
+            if name_index >= 258 {
+                assert_eq!(name, names[name_index - 258]);
+            }
+        }
+        assert_eq!(face.glyph_name(ttf_parser::GlyphId(1)), Some("NULL"));
+        assert_eq!(face.glyph_name(ttf_parser::GlyphId(5)), Some("quotedbl"));
+    }
+
+    #[test]
+    fn read_cff_glyph() {
+        /// Counts the path segments of an outline.
+        struct SegmentCounter(usize);
+
+        impl ttf_parser::OutlineBuilder for SegmentCounter {
+            fn move_to(&mut self, _: f32, _: f32) {}
+            fn line_to(&mut self, _: f32, _: f32) {
+                self.0 += 1;
+            }
+            fn quad_to(&mut self, _: f32, _: f32, _: f32, _: f32) {
+                self.0 += 1;
+            }
+            fn curve_to(&mut self, _: f32, _: f32, _: f32, _: f32, _: f32, _: f32) {
+                self.0 += 1;
+            }
+            fn close(&mut self) {}
+        }
+
+        let ttf = convert_woff2_to_ttf(&mut Cursor::new(TEST_KERN_ONE_CFF)).unwrap();
+        // the `CFF ` table is padded in the file, but keeps its original length in the table
+        // directory, so its internal offsets still line up
+        let raw_face = ttf_parser::RawFace::from_slice(&ttf, 0).unwrap();
+        let cff = raw_face
+            .table(ttf_parser::Tag::from_bytes(b"CFF "))
+            .unwrap();
+        assert_eq!(cff.len(), 358);
+
+        let face = ttf_parser::Face::from_slice(&ttf, 0).unwrap();
+        let glyph = face.glyph_index('T').unwrap();
+        let mut segments = SegmentCounter(0);
+        let bbox = face.outline_glyph(glyph, &mut segments).unwrap();
+        assert_eq!(
+            (bbox.x_min, bbox.y_min, bbox.x_max, bbox.y_max),
+            (50, 0, 550, 700)
+        );
+        assert!(segments.0 > 0);
+    }
+
+    #[test]
+    // Spec: https://www.w3.org/TR/WOFF2/#table_order
+    // The loca table MUST follow the glyf table in the table directory. When WOFF2 file contains individually encoded font file, the table directory MAY contain other tables inserted between glyf and loca tables; however when WOFF2 contains a font collection file each loca table MUST immediately follow its corresponding glyf table. For example, the following order of tables: 'cmap', 'glyf', 'hhea', 'hmtx', 'loca', 'maxp' ... is acceptable for individually encoded font files;
+    fn read_loca_is_not_after_glyf_font() {
+        // In this test font, the loca table does not follow the glyf table.
+        let buffer = FONTAWESOME_REGULAR_400;
+        let ttf = convert_woff2_to_ttf(&mut Cursor::new(buffer)).unwrap();
+        assert_eq!(None, ttf_parser::fonts_in_collection(&ttf));
+        let _parsed_ttf = ttf_parser::Face::from_slice(&ttf, 1).unwrap();
+    }
+
+    #[test]
+    fn read_transformed_hmtx_fonts() {
+        let expected = convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        for font in [
+            LATO_V22_LATIN_REGULAR_HMTX_IDENTITY,
+            LATO_V22_LATIN_REGULAR_HMTX_TRANSFORMED,
+        ] {
+            assert_eq!(
+                convert_woff2_to_ttf(&mut Cursor::new(font)).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn decode_glyf_loca_matches_full_decode() {
+        for font in [LATO_V22_LATIN_REGULAR, FONTAWESOME_REGULAR_400] {
+            let (glyf, loca) = decode_glyf_loca(&mut Cursor::new(font)).unwrap();
+            let ttf = convert_woff2_to_ttf(&mut Cursor::new(font)).unwrap();
+            let face = ttf_parser::RawFace::from_slice(&ttf, 0).unwrap();
+            assert_eq!(
+                Some(&glyf[..]),
+                face.table(ttf_parser::Tag::from_bytes(b"glyf"))
+            );
+            assert_eq!(
+                Some(&loca[..]),
+                face.table(ttf_parser::Tag::from_bytes(b"loca"))
+            );
+        }
+    }
+
+    #[test]
+    fn skip_checksums() {
+        let options = DecodeOptions::new().compute_checksums(false).build();
+        let mut expected = convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        let ttf =
+            convert_woff2_to_ttf_with_options(&mut Cursor::new(LATO_V22_LATIN_REGULAR), &options)
+                .unwrap();
+        ttf_parser::Face::from_slice(&ttf, 0).unwrap();
+
+        // the same font, but with zero checksums
+        let num_tables = u16::from_be_bytes([expected[4], expected[5]]) as usize;
+        for index in 0..num_tables {
+            let record = 12 + 16 * index;
+            expected[record + 4..record + 8].fill(0);
+            if &expected[record..record + 4] == b"head" {
+                let offset =
+                    u32::from_be_bytes(expected[record + 8..record + 12].try_into().unwrap());
+                let offset = offset as usize;
+                expected[offset + 8..offset + 12].fill(0);
+            }
+        }
+        assert_eq!(ttf, expected);
+    }
+
+    #[test]
+    fn lenient_compressed_size() {
+        let expected = convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        let lenient = DecodeOptions::new().lenient_compressed_size(true).build();
+        let compressed_size =
+            u32::from_be_bytes(LATO_V22_LATIN_REGULAR[20..24].try_into().unwrap());
+        // too large to fit in the file, and too small for the brotli stream
+        for declared_size in [compressed_size + 8, compressed_size - 5] {
+            let mut font = LATO_V22_LATIN_REGULAR.to_vec();
+            font[20..24].copy_from_slice(&declared_size.to_be_bytes());
+            assert!(convert_woff2_to_ttf(&mut Cursor::new(&font)).is_err());
+            assert_eq!(
+                convert_woff2_to_ttf_with_options(&mut Cursor::new(&font), &lenient).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn strict_sfnt_size() {
+        let strict = DecodeOptions::new().strict(true).build();
+        for font in [
+            LATO_V22_LATIN_REGULAR,
+            LATO_V22_LATIN_REGULAR_HMTX_TRANSFORMED,
+            FONTAWESOME_REGULAR_400,
+        ] {
+            convert_woff2_to_ttf_with_options(&mut Cursor::new(font), &strict).unwrap();
+        }
+
+        let mut font = LATO_V22_LATIN_REGULAR.to_vec();
+        // total_sfnt_size
+        font[16..20].copy_from_slice(&60720u32.to_be_bytes());
+        convert_woff2_to_ttf(&mut Cursor::new(&font)).unwrap();
+        assert!(matches!(
+            convert_woff2_to_ttf_with_options(&mut Cursor::new(&font), &strict),
+            Err(DecodeError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn classify_errors() {
+        let error = convert_woff2_to_ttf(&mut Cursor::new(b"wOF2")).unwrap_err();
+        assert!(error.is_corrupt() && !error.is_unsupported());
+
+        let font = woff2_collection_with_tables(&[b"OS/2"], &[&[0]]);
+        let error = super::decode_tables(&mut Cursor::new(font)).unwrap_err();
+        assert!(error.is_unsupported() && !error.is_corrupt());
+
+        let options = DecodeOptions::new().max_decompressed_size(1).build();
+        let error =
+            convert_woff2_to_ttf_with_options(&mut Cursor::new(LATO_V22_LATIN_REGULAR), &options)
+                .unwrap_err();
+        assert!(!error.is_corrupt() && !error.is_unsupported());
+    }
+
+    /// Rebuilds `font` with its tables in the order `reorder` leaves them in, and the table data
+    /// stored in the brotli stream without compression.
+    fn rebuild_stored(
+        font: &[u8],
+        reorder: impl FnOnce(&mut Vec<crate::woff2::table_directory::TableDirectoryEntry>),
+    ) -> Vec<u8> {
+        use bytes::BufMut;
+
+        use super::decompress_woff2;
+        use crate::{
+            buffer_util::BufMutExt,
+            woff2::table_directory::{GLYF_TAG, LOCA_TAG},
+        };
+
+        let decompressed =
+            decompress_woff2(&mut Cursor::new(font), &DecodeOptions::default()).unwrap();
+        let mut tables = decompressed.table_directory.tables.clone();
+        reorder(&mut tables);
+        // keep the header; the metadata and private data blocks are dropped
+        let mut rebuilt = font[..48].to_vec();
+        rebuilt[28..48].fill(0);
+        let mut table_data = Vec::new();
+        for table in &tables {
+            // the null transform is version 3 for `glyf` and `loca`, and version 0 otherwise
+            let version = match table.tag {
+                GLYF_TAG | LOCA_TAG if table.transformed => 0,
+                GLYF_TAG | LOCA_TAG => 0xC0,
+                _ if table.transformed => 0x40,
+                _ => 0,
+            };
+            rebuilt.put_u8(version | 0x3F);
+            rebuilt.put_slice(&table.tag.0);
+            rebuilt.put_base_128(table.dest_length);
+            if table.transformed {
+                rebuilt.put_base_128(table.src_length);
+            }
+            table_data
+                .extend_from_slice(&decompressed.decompressed_tables[table.get_source_range()]);
+        }
+        let compressed = stored_brotli_stream(&table_data);
+        rebuilt[20..24].copy_from_slice(&(compressed.len() as u32).to_be_bytes());
+        rebuilt.extend_from_slice(&compressed);
+        rebuilt.resize((rebuilt.len() + 3) & !3, 0);
+        let length = rebuilt.len() as u32;
+        rebuilt[8..12].copy_from_slice(&length.to_be_bytes());
+        rebuilt
+    }
+
+    #[test]
+    fn read_stored_stream() {
+        let strict = DecodeOptions::new().strict(true).build();
+        for font in [
+            LATO_V22_LATIN_REGULAR,
+            FONTAWESOME_REGULAR_400,
+            TEST_KERN_ONE_CFF,
+        ] {
+            let stored = rebuild_stored(font, |_| {});
+            assert_eq!(
+                convert_woff2_to_ttf_with_options(&mut Cursor::new(&stored), &strict).unwrap(),
+                convert_woff2_to_ttf(&mut Cursor::new(font)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn reject_stored_stream_ending_early() {
+        let stored = rebuild_stored(LATO_V22_LATIN_REGULAR, |_| {});
+        // the stream ends with the ISLAST byte, and is followed by zero padding
+        let stream_end = stored.iter().rposition(|&byte| byte != 0).unwrap() + 1;
+        let mut font = stored[..stream_end].to_vec();
+        font.extend_from_slice(&[1, 2, 3, 4, 5]);
+        let compressed_size = u32::from_be_bytes(font[20..24].try_into().unwrap()) + 5;
+        font[20..24].copy_from_slice(&compressed_size.to_be_bytes());
+        let font = resize_font(&font, (font.len() + 3) & !3);
+        assert!(matches!(
+            convert_woff2_to_ttf(&mut Cursor::new(font)),
+            Err(DecodeError::Invalid(message)) if message == "Unexpected data after compressed stream"
+        ));
+    }
+
+    #[test]
+    fn glyph_count_mismatch() {
+        let ttf = convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        let maxp = ttf_parser::RawFace::from_slice(&ttf, 0)
+            .unwrap()
+            .table(ttf_parser::Tag::from_bytes(b"maxp"))
+            .unwrap();
+        let mut font = rebuild_stored(LATO_V22_LATIN_REGULAR, |_| {});
+        assert!(convert_woff2_to_ttf(&mut Cursor::new(&font)).is_ok());
+
+        // the stored stream holds `maxp` as is
+        let offset = font
+            .windows(maxp.len())
+            .position(|window| window == maxp)
+            .unwrap();
+        font[offset + 5] ^= 1;
+        assert!(matches!(
+            convert_woff2_to_ttf(&mut Cursor::new(&font)),
+            Err(DecodeError::Invalid(message)) if message == "maxp/glyf glyph count mismatch"
+        ));
+    }
+
+    #[test]
+    fn index_format_mismatch() {
+        let expected = convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        // the table data is stored as it is, so `head` can be found by its magic number
+        let mut font = rebuild_stored(LATO_V22_LATIN_REGULAR, |_| {});
+        let magic = font
+            .windows(4)
+            .position(|window| window == [0x5F, 0x0F, 0x3C, 0xF5])
+            .unwrap();
+        // `indexToLocFormat`, at offset 50 in `head`
+        let index_to_loc_format = magic - 12 + 51;
+        assert!(font[index_to_loc_format] <= 1);
+        font[index_to_loc_format] ^= 1;
+
+        let strict = DecodeOptions::new().strict(true).build();
+        assert!(matches!(
+            convert_woff2_to_ttf_with_options(&mut Cursor::new(&font), &strict),
+            Err(DecodeError::Invalid(message)) if message == "indexToLocFormat mismatch"
+        ));
+        // otherwise the `glyf` header wins, and `head` is rewritten to match it
+        assert_eq!(
+            convert_woff2_to_ttf(&mut Cursor::new(&font)).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn head_after_glyf_and_loca() {
+        use super::list_tables;
+        use crate::{checksum::calculate_checksum, woff2::table_directory::HEAD_TAG};
+
+        // rebuild the sample font with `head` moved to the end of the table directory
+        let mut tables = Vec::new();
+        let font = rebuild_stored(LATO_V22_LATIN_REGULAR, |reordered| {
+            let head_index = reordered
+                .iter()
+                .position(|table| table.tag == HEAD_TAG)
+                .unwrap();
+            let head = reordered.remove(head_index);
+            reordered.push(head);
+            tables = reordered.clone();
+        });
+        assert_eq!(
+            list_tables(&mut Cursor::new(&font)).unwrap().last(),
+            Some(&HEAD_TAG)
+        );
+
+        let strict = DecodeOptions::new().strict(true).build();
+        let ttf = convert_woff2_to_ttf_with_options(&mut Cursor::new(&font), &strict).unwrap();
+        assert_eq!(calculate_checksum(&ttf), 0xB1B0AFBA);
+        let expected = convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        let face = ttf_parser::RawFace::from_slice(&ttf, 0).unwrap();
+        let expected_face = ttf_parser::RawFace::from_slice(&expected, 0).unwrap();
+        for table in &tables {
+            let tag = ttf_parser::Tag::from_bytes(&table.tag.0);
+            if table.tag == HEAD_TAG {
+                // only the checksum adjustment differs
+                assert_eq!(
+                    face.table(tag).unwrap()[12..],
+                    expected_face.table(tag).unwrap()[12..]
+                );
+            } else {
+                assert_eq!(face.table(tag), expected_face.table(tag));
+            }
+        }
+        assert!(ttf_parser::Face::from_slice(&ttf, 0).is_ok());
+    }
+
+    #[test]
+    fn transformed_hmtx_before_glyf_and_hhea() {
+        use super::list_tables;
+        use crate::woff2::table_directory::{HEAD_TAG, HMTX_TAG};
+
+        let font = rebuild_stored(LATO_V22_LATIN_REGULAR_HMTX_TRANSFORMED, |reordered| {
+            let hmtx_index = reordered
+                .iter()
+                .position(|table| table.tag == HMTX_TAG)
+                .unwrap();
+            assert!(reordered[hmtx_index].transformed);
+            let hmtx = reordered.remove(hmtx_index);
+            reordered.insert(0, hmtx);
+        });
+
+        let ttf = convert_woff2_to_ttf(&mut Cursor::new(&font)).unwrap();
+        let expected =
+            convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR_HMTX_TRANSFORMED))
+                .unwrap();
+        let face = ttf_parser::RawFace::from_slice(&ttf, 0).unwrap();
+        let expected_face = ttf_parser::RawFace::from_slice(&expected, 0).unwrap();
+        for tag in list_tables(&mut Cursor::new(&font)).unwrap() {
+            if tag != HEAD_TAG {
+                let tag = ttf_parser::Tag::from_bytes(&tag.0);
+                assert_eq!(face.table(tag), expected_face.table(tag));
+            }
+        }
+
+        #[cfg(feature = "streaming")]
+        assert!(matches!(
+            super::convert_woff2_to_ttf_streaming(
+                &mut Cursor::new(&font),
+                &DecodeOptions::default()
+            ),
+            Err(DecodeError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn decode_font_without_head() {
+        let font = woff2_with_tables(&[b"OS/2", b"name"]);
+        let ttf = convert_woff2_to_ttf(&mut Cursor::new(&font)).unwrap();
+        let face = ttf_parser::RawFace::from_slice(&ttf, 0).unwrap();
+        // numTables
+        assert_eq!(ttf[4..6], [0, 2]);
+        assert_eq!(
+            face.table(ttf_parser::Tag::from_bytes(b"name")),
+            Some(&[0; 4][..])
+        );
+
+        let strict = DecodeOptions::new().strict(true).build();
+        assert!(matches!(
+            convert_woff2_to_ttf_with_options(&mut Cursor::new(&font), &strict),
+            Err(DecodeError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn null_dsig_table() {
+        // version 1, one signature (format 1, 12 bytes at offset 20), flags 1, then the signature
+        let mut dsig = vec![0, 0, 0, 1, 0, 1, 0, 1, 0, 0, 0, 1, 0, 0, 0, 12, 0, 0, 0, 20];
+        dsig.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 4, 0xDE, 0xAD, 0xBE, 0xEF]);
+        let font = woff2_with_table_data(&[(b"DSIG", &dsig), (b"cmap", &[0; 4])]);
+        let dsig_of = |ttf: &[u8]| {
+            let face = ttf_parser::RawFace::from_slice(ttf, 0).unwrap();
+            face.table(ttf_parser::Tag::from_bytes(b"DSIG"))
+                .unwrap()
+                .to_vec()
+        };
+
+        // the checksums are verified after the table is nulled
+        let ttf = convert_woff2_to_ttf(&mut Cursor::new(&font)).unwrap();
+        let mut expected = vec![0; dsig.len()];
+        expected[3] = 1;
+        assert_eq!(expected, dsig_of(&ttf));
+
+        let options = DecodeOptions::new().strip_dsig(false).build();
+        let ttf = convert_woff2_to_ttf_with_options(&mut Cursor::new(&font), &options).unwrap();
+        assert_eq!(dsig, dsig_of(&ttf));
+    }
+
+    #[test]
+    fn strict_requires_cmap() {
+        let strict = DecodeOptions::new().strict(true).build();
+        let font = woff2_with_tables(&[b"OS/2", b"name"]);
+        match convert_woff2_to_ttf_with_options(&mut Cursor::new(&font), &strict) {
+            Err(DecodeError::Invalid(message)) => assert!(message.contains("cmap"), "{}", message),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let font = woff2_with_tables(&[b"OS/2", b"cmap", b"name"]);
+        assert!(convert_woff2_to_ttf(&mut Cursor::new(&font)).is_ok());
+        match convert_woff2_to_ttf_with_options(&mut Cursor::new(&font), &strict) {
+            Err(DecodeError::Invalid(message)) => assert!(message.contains("head"), "{}", message),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reject_unexpected_flavor() {
+        use crate::magic_numbers::{TTF_CFF_FLAVOR, TTF_COLLECTION_FLAVOR, TTF_TRUE_TYPE_FLAVOR};
+
+        let true_type = DecodeOptions::new()
+            .expected_flavor(TTF_TRUE_TYPE_FLAVOR)
+            .build();
+        assert!(convert_woff2_to_ttf_with_options(
+            &mut Cursor::new(LATO_V22_LATIN_REGULAR),
+            &true_type
+        )
+        .is_ok());
+        let collection = woff2_collection_with_tables(&[b"cmap", b"head"], &[&[0, 1]]);
+        for (font, flavor) in [
+            (TEST_KERN_ONE_CFF, TTF_CFF_FLAVOR),
+            (&collection[..], TTF_COLLECTION_FLAVOR),
+        ] {
+            let error =
+                convert_woff2_to_ttf_with_options(&mut Cursor::new(font), &true_type).unwrap_err();
+            assert!(matches!(
+                error,
+                DecodeError::BadFlavor { flavor: f, expected } if f == flavor && expected == TTF_TRUE_TYPE_FLAVOR
+            ));
+            assert!(!error.is_corrupt());
+        }
+
+        #[cfg(feature = "streaming")]
+        assert!(matches!(
+            super::convert_woff2_to_ttf_streaming(&mut Cursor::new(TEST_KERN_ONE_CFF), &true_type),
+            Err(DecodeError::BadFlavor { .. })
+        ));
+    }
+
+    #[test]
+    fn decode_bitmap_font() {
+        use crate::checksum::calculate_checksum;
+
+        let tables = bitmap_font_tables();
+        let table_data: Vec<_> = tables.iter().map(|(tag, data)| (*tag, &data[..])).collect();
+        let font = woff2_with_table_data(&table_data);
+        let ttf = convert_woff2_to_ttf(&mut Cursor::new(&font)).unwrap();
+        assert_eq!(calculate_checksum(&ttf), 0xB1B0AFBA);
+        let face = ttf_parser::RawFace::from_slice(&ttf, 0).unwrap();
+        for (tag, data) in &tables[..3] {
+            assert_eq!(
+                face.table(ttf_parser::Tag::from_bytes(tag)),
+                Some(&data[..])
+            );
+        }
+
+        // `EBLC` offsets are relative to the start of `EBDT`, so padding doesn't move the glyphs
+        let eblc = face.table(ttf_parser::Tag::from_bytes(b"EBLC")).unwrap();
+        let ebdt = face.table(ttf_parser::Tag::from_bytes(b"EBDT")).unwrap();
+        let read_u32 = |offset: usize| {
+            u32::from_be_bytes(eblc[offset..offset + 4].try_into().unwrap()) as usize
+        };
+        let subtable = read_u32(8) + read_u32(8 + 48 + 4);
+        let image_data_offset = read_u32(subtable + 4);
+        let glyph_2 = image_data_offset + read_u32(subtable + 12)
+            ..image_data_offset + read_u32(subtable + 16);
+        assert_eq!(ebdt[glyph_2], [2, 8, 0, 2, 8, 0b1000_0001, 0b0111_1110]);
+    }
+
+    #[test]
+    fn decode_variable_font() {
+        use ttf_parser::{Face, GlyphId, Tag};
+
+        struct NoOutline;
+        impl ttf_parser::OutlineBuilder for NoOutline {
+            fn move_to(&mut self, _: f32, _: f32) {}
+            fn line_to(&mut self, _: f32, _: f32) {}
+            fn quad_to(&mut self, _: f32, _: f32, _: f32, _: f32) {}
+            fn curve_to(&mut self, _: f32, _: f32, _: f32, _: f32, _: f32, _: f32) {}
+            fn close(&mut self) {}
+        }
+
+        let tables = variable_font_tables();
+        let table_data: Vec<_> = tables.iter().map(|(tag, data)| (*tag, &data[..])).collect();
+        let font = woff2_with_table_data(&table_data);
+        let ttf = convert_woff2_to_ttf(&mut Cursor::new(&font)).unwrap();
+        let mut face = Face::from_slice(&ttf, 0).unwrap();
+        assert!(face.is_variable());
+        let raw_face = ttf_parser::RawFace::from_slice(&ttf, 0).unwrap();
+        for tag in [b"fvar", b"gvar"] {
+            let (_, data) = tables.iter().find(|(t, _)| t == &tag).unwrap();
+            assert_eq!(raw_face.table(Tag::from_bytes(tag)), Some(&data[..]));
+        }
+
+        let axes: Vec<_> = face.variation_axes().into_iter().collect();
+        assert_eq!(axes.len(), 1);
+        assert_eq!(axes[0].tag, Tag::from_bytes(b"wght"));
+        assert_eq!(
+            (axes[0].min_value, axes[0].def_value, axes[0].max_value),
+            (100.0, 400.0, 900.0)
+        );
+
+        let x_bounds = |face: &Face| {
+            let bbox = face.outline_glyph(GlyphId(0), &mut NoOutline).unwrap();
+            (bbox.x_min, bbox.x_max)
+        };
+        assert_eq!(x_bounds(&face), (0, 100));
+        // the coordinates of the named instance
+        let fvar = raw_face.table(Tag::from_bytes(b"fvar")).unwrap();
+        let weight = i32::from_be_bytes(fvar[40..44].try_into().unwrap()) as f32 / 65536.0;
+        face.set_variation(Tag::from_bytes(b"wght"), weight)
+            .unwrap();
+        assert_eq!(x_bounds(&face), (50, 150));
+    }
+
+    #[test]
+    fn reject_duplicate_tables() {
+        let font = woff2_with_tables(&[b"OS/2", b"name", b"name"]);
+        assert!(matches!(
+            convert_woff2_to_ttf(&mut Cursor::new(&font)),
+            Err(DecodeError::TableDirectory(TableDirectoryError::DuplicateTag(tag))) if &tag.0 == b"name"
+        ));
+        // fonts in a collection can share tables
+        let font = woff2_collection_with_tables(&[b"OS/2", b"name"], &[&[0, 1], &[0, 1]]);
+        assert!(convert_woff2_to_ttf(&mut Cursor::new(&font)).is_ok());
+        let font = woff2_collection_with_tables(&[b"OS/2", b"name", b"name"], &[&[0, 1], &[1, 2]]);
+        assert!(convert_woff2_to_ttf(&mut Cursor::new(&font)).is_err());
+    }
+
+    #[test]
+    fn rebuild_lone_transformed_loca() {
+        use crate::woff2::table_directory::WriteTablesError;
+
+        // a triangle, padded to four bytes, and a composite glyph using it
+        let mut glyf = vec![0, 1, 0, 0, 0, 0, 0, 10, 0, 10, 0, 2, 0, 0, 0x37, 0x37, 0x37];
+        glyf.extend_from_slice(&[0, 10, 0, 0, 0, 10, 0]);
+        glyf.extend_from_slice(&[0xFF, 0xFF, 0, 0, 0, 0, 0, 10, 0, 10, 0, 2, 0, 0, 5, 5]);
+        let mut head = vec![0; 54];
+        head[12..16].copy_from_slice(&[0x5F, 0x0F, 0x3C, 0xF5]);
+        let font = woff2_with_transformed_loca(&[(b"glyf", &glyf), (b"head", &head)], 6);
+
+        assert!(matches!(
+            convert_woff2_to_ttf(&mut Cursor::new(&font)),
+            Err(DecodeError::Tables(
+                WriteTablesError::GlyfLocaDifferentTransform { .. }
+            ))
+        ));
+        let lenient = DecodeOptions::new().lenient_loca_transform(true).build();
+        let ttf = convert_woff2_to_ttf_with_options(&mut Cursor::new(&font), &lenient).unwrap();
+        let face = ttf_parser::RawFace::from_slice(&ttf, 0).unwrap();
+        assert_eq!(
+            face.table(ttf_parser::Tag::from_bytes(b"loca")),
+            Some(&[0, 0, 0, 12, 0, 20][..])
+        );
+        assert_eq!(
+            face.table(ttf_parser::Tag::from_bytes(b"glyf")),
+            Some(&glyf[..])
+        );
+
+        let strict = DecodeOptions::new()
+            .lenient_loca_transform(true)
+            .strict(true)
+            .build();
+        assert!(convert_woff2_to_ttf_with_options(&mut Cursor::new(&font), &strict).is_err());
+    }
+
+    #[test]
+    fn physical_table_order() {
+        let offsets = |ttf: &[u8]| {
+            let face = ttf_parser::RawFace::from_slice(ttf, 0).unwrap();
+            [b"glyf", b"hmtx", b"loca"].map(|tag| {
+                let table = face.table(ttf_parser::Tag::from_bytes(tag)).unwrap();
+                table.as_ptr() as usize - ttf.as_ptr() as usize
+            })
+        };
+        let font = woff2_with_tables(&[b"cmap", b"glyf", b"hmtx", b"loca"]);
+        let ttf = convert_woff2_to_ttf(&mut Cursor::new(&font)).unwrap();
+        // `loca` is moved up after `glyf`
+        let [glyf, hmtx, loca] = offsets(&ttf);
+        assert!(glyf < loca && loca < hmtx);
+
+        let options = DecodeOptions::new().physical_table_order(true).build();
+        let physical =
+            convert_woff2_to_ttf_with_options(&mut Cursor::new(&font), &options).unwrap();
+        let [glyf, hmtx, loca] = offsets(&physical);
+        assert!(glyf < hmtx && hmtx < loca);
+        // the table directories only differ in the offsets
+        assert_eq!(ttf.len(), physical.len());
+        assert!(ttf_parser::RawFace::from_slice(&physical, 0).is_ok());
+
+        // nothing changes when `loca` already follows `glyf`
+        let ttf = convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        let physical =
+            convert_woff2_to_ttf_with_options(&mut Cursor::new(LATO_V22_LATIN_REGULAR), &options)
+                .unwrap();
+        assert_eq!(ttf, physical);
+    }
+
+    #[test]
+    fn max_points_per_glyph() {
+        use crate::{glyf_decoder::GlyfDecoderError, woff2::table_directory::WriteTablesError};
+
+        let options = DecodeOptions::new().max_points_per_glyph(10).build();
+        let result =
+            convert_woff2_to_ttf_with_options(&mut Cursor::new(LATO_V22_LATIN_REGULAR), &options);
+        assert!(matches!(
+            result,
+            Err(DecodeError::Tables(WriteTablesError::GlyfDecoderError(
+                GlyfDecoderError::TooManyPoints { limit: 10, .. }
+            )))
+        ));
+
+        #[cfg(feature = "streaming")]
+        assert!(matches!(
+            super::convert_woff2_to_ttf_streaming(
+                &mut Cursor::new(LATO_V22_LATIN_REGULAR),
+                &options
+            ),
+            Err(DecodeError::Tables(WriteTablesError::GlyfDecoderError(
+                GlyfDecoderError::TooManyPoints { limit: 10, .. }
+            )))
+        ));
+    }
+
+    #[test]
+    fn skip_glyf_transform() {
+        let options = DecodeOptions::new().skip_glyf_transform(true).build();
+        for font in [
+            LATO_V22_LATIN_REGULAR,
+            LATO_V22_LATIN_REGULAR_HMTX_TRANSFORMED,
+        ] {
+            let decompressed =
+                super::decompress_woff2(&mut Cursor::new(font), &DecodeOptions::default()).unwrap();
+            let ttf = convert_woff2_to_ttf_with_options(&mut Cursor::new(font), &options).unwrap();
+            let face = ttf_parser::RawFace::from_slice(&ttf, 0).unwrap();
+            for table in decompressed
+                .table_directory
+                .tables
+                .iter()
+                .filter(|table| table.transformed)
+            {
+                let data = face.table(ttf_parser::Tag::from_bytes(&table.tag.0));
+                assert_eq!(
+                    data,
+                    Some(&decompressed.decompressed_tables[table.get_source_range()]),
+                    "`{}`",
+                    table.tag
+                );
+            }
+            // the transformed `loca` table has no data
+            assert_eq!(
+                face.table(ttf_parser::Tag::from_bytes(b"loca")),
+                Some(&[][..])
+            );
+
+            #[cfg(feature = "streaming")]
+            assert_eq!(
+                super::convert_woff2_to_ttf_streaming(&mut Cursor::new(font), &options).unwrap(),
+                ttf
+            );
+        }
+    }
+
+    #[test]
+    fn directory_past_declared_length() {
+        // two entries of six bytes each, but the declared length ends in the second one
+        let mut font = woff2_with_tables(&[b"OS/2", b"name"]);
+        font[8..12].copy_from_slice(&(48u32 + 8).to_be_bytes());
+        assert!(matches!(
+            convert_woff2_to_ttf(&mut Cursor::new(&font)),
+            Err(DecodeError::TableDirectory(
+                TableDirectoryError::Truncated { offset: 54 }
+            ))
+        ));
+        assert!(matches!(
+            Woff2TableDirectory::from_slice(&font, 48, &Woff2Header::from_slice(&font).unwrap().0),
+            Err(TableDirectoryError::Truncated { offset: 54 })
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn read_file() {
+        use super::convert_woff2_file_to_ttf;
+
+        let path =
+            std::env::temp_dir().join(format!("woff2-read-file-{}.woff2", std::process::id()));
+        std::fs::write(&path, LATO_V22_LATIN_REGULAR).unwrap();
+        let ttf = convert_woff2_file_to_ttf(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            ttf.unwrap(),
+            convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap()
+        );
+
+        let error = convert_woff2_file_to_ttf(&path).unwrap_err();
+        assert!(
+            matches!(&error, DecodeError::Io(error) if error.kind() == std::io::ErrorKind::NotFound)
+        );
+        assert!(!error.is_corrupt());
+    }
+
+    #[test]
+    fn read_bytes() {
+        use bytes::Bytes;
+
+        use super::convert_woff2_bytes_to_ttf;
+
+        let expected = convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        let input = Bytes::from_static(LATO_V22_LATIN_REGULAR);
+        assert_eq!(convert_woff2_bytes_to_ttf(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn slice_compressed_stream_from_bytes() {
+        use bytes::Bytes;
+
+        use super::split_compressed_stream;
+
+        let input = Bytes::from(LATO_V22_LATIN_REGULAR.to_vec());
+        let mut rest = input.clone();
+        let (headers, compressed_stream) =
+            split_compressed_stream(&mut rest, &DecodeOptions::default()).unwrap();
+        assert_eq!(compressed_stream.len(), headers.compressed_size);
+        // the stream points into `input` rather than at a copy of it
+        let stream_start = input.len() - rest.len() - compressed_stream.len();
+        assert_eq!(compressed_stream.as_ptr(), input[stream_start..].as_ptr());
+    }
+
+    #[test]
+    fn reuse_output_buffer() {
+        use super::convert_woff2_to_ttf_into;
+
+        let mut out_buffer = Vec::new();
+        for font in [
+            LATO_V22_LATIN_REGULAR,
+            TEST_KERN_ONE_CFF,
+            FONTAWESOME_REGULAR_400,
+        ] {
+            convert_woff2_to_ttf_into(&mut Cursor::new(font), &mut out_buffer).unwrap();
+            assert_eq!(
+                out_buffer,
+                convert_woff2_to_ttf(&mut Cursor::new(font)).unwrap()
+            );
+        }
+        let capacity = out_buffer.capacity();
+        convert_woff2_to_ttf_into(&mut Cursor::new(TEST_KERN_ONE_CFF), &mut out_buffer).unwrap();
+        assert_eq!(out_buffer.capacity(), capacity);
+
+        // a font that fails to decode doesn't cost the allocation
+        let truncated = &LATO_V22_LATIN_REGULAR[..LATO_V22_LATIN_REGULAR.len() / 2];
+        assert!(convert_woff2_to_ttf_into(&mut Cursor::new(truncated), &mut out_buffer).is_err());
+        assert!(out_buffer.is_empty());
+        assert_eq!(out_buffer.capacity(), capacity);
+    }
+
+    #[test]
+    fn read_segmented_buffer() {
+        use bytes::Buf;
+
+        let expected = convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        let len = LATO_V22_LATIN_REGULAR.len();
+        // split in the header, the table directory, the compressed stream and the padding
+        for split in [1, 21, 50, 100, 1000, len / 2, len - 1] {
+            let (first, second) = LATO_V22_LATIN_REGULAR.split_at(split);
+            let mut buffer = first.chain(second);
+            assert_eq!(convert_woff2_to_ttf(&mut buffer).unwrap(), expected);
+            assert!(!buffer.has_remaining());
+        }
+    }
+
+    #[test]
+    fn read_cff_font() {
+        use four_cc::FourCC;
+
+        let options = DecodeOptions::new().strict(true).build();
+        let (ttf, info) =
+            convert_woff2_to_ttf_with_header(&mut Cursor::new(TEST_KERN_ONE_CFF), &options)
+                .unwrap();
+        assert_eq!(info.flavor, FourCC(*b"OTTO"));
+        let face = ttf_parser::Face::from_slice(&ttf, 0).unwrap();
+        assert!(face.tables().cff.is_some());
+        assert!(face.tables().glyf.is_none());
+        let raw_face = ttf_parser::RawFace::from_slice(&ttf, 0).unwrap();
+        assert!(raw_face
+            .table(ttf_parser::Tag::from_bytes(b"loca"))
+            .is_none());
+        // maxp version 0.5
+        let maxp = raw_face
+            .table(ttf_parser::Tag::from_bytes(b"maxp"))
+            .unwrap();
+        assert_eq!(maxp[..4], [0, 0, 0x50, 0]);
+        assert!(face.glyph_bounding_box(ttf_parser::GlyphId(1)).is_some());
+    }
+
+    #[test]
+    fn list_sample_font_tables() {
+        use super::list_tables;
+
+        let tags = list_tables(&mut Cursor::new(FONTAWESOME_REGULAR_400)).unwrap();
+        assert_eq!(tags.len(), 13);
+        assert_eq!(&tags[5].0, b"glyf");
+        assert_eq!(&tags[9].0, b"loca");
+    }
+
+    #[test]
+    fn read_raw_transformed_tables() {
+        use four_cc::FourCC;
+
+        use super::raw_transformed_table;
+        use crate::glyf_decoder::{decode_glyf_table, decode_glyf_table_from_reader};
+
+        let raw = |tag: &[u8; 4]| {
+            raw_transformed_table(&mut Cursor::new(LATO_V22_LATIN_REGULAR), FourCC(*tag)).unwrap()
+        };
+        let (glyf, loca) = decode_glyf_loca(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        let decoded = decode_glyf_table(&raw(b"glyf").unwrap()).unwrap();
+        assert_eq!(decoded.glyf, glyf);
+        assert_eq!(decoded.loca, loca);
+        let from_reader =
+            decode_glyf_table_from_reader(Cursor::new(raw(b"glyf").unwrap())).unwrap();
+        assert_eq!(from_reader.glyf, glyf);
+        assert_eq!(from_reader.loca, loca);
+        // the transformed `loca` table is empty
+        assert_eq!(raw(b"loca"), Some(Vec::new()));
+        assert_eq!(raw(b"CFF "), None);
+
+        let ttf = convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        let face = ttf_parser::RawFace::from_slice(&ttf, 0).unwrap();
+        assert_eq!(
+            raw(b"name").as_deref(),
+            face.table(ttf_parser::Tag::from_bytes(b"name"))
+        );
+    }
+
+    #[test]
+    fn count_glyphs() {
+        use super::glyph_count;
+
+        // fonts with transformed `glyf` tables and a CFF font
+        for font in [
+            LATO_V22_LATIN_REGULAR,
+            FONTAWESOME_REGULAR_400,
+            TEST_KERN_ONE_CFF,
+        ] {
+            let ttf = convert_woff2_to_ttf(&mut Cursor::new(font)).unwrap();
+            let face = ttf_parser::Face::from_slice(&ttf, 0).unwrap();
+            assert_eq!(
+                face.number_of_glyphs(),
+                glyph_count(&mut Cursor::new(font)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn report_glyph_presence() {
+        use super::glyph_presence;
+
+        for font in [LATO_V22_LATIN_REGULAR, FONTAWESOME_REGULAR_400] {
+            let ttf = convert_woff2_to_ttf(&mut Cursor::new(font)).unwrap();
+            let face = ttf_parser::Face::from_slice(&ttf, 0).unwrap();
+            let expected: Vec<_> = (0..face.number_of_glyphs())
+                .map(|glyph_index| {
+                    face.glyph_bounding_box(ttf_parser::GlyphId(glyph_index))
+                        .is_some()
+                })
+                .collect();
+            let presence = glyph_presence(&mut Cursor::new(font)).unwrap();
+            assert_eq!(presence, expected);
+            // the space
+            assert!(presence.contains(&false));
+        }
+
+        // a partial font with only glyph 1, and untransformed `glyf` and `loca`
+        let mut head = vec![0; 54];
+        head[12..16].copy_from_slice(&[0x5F, 0x0F, 0x3C, 0xF5]);
+        let font = woff2_with_table_data(&[
+            (b"glyf", &[0; 12]),
+            (b"head", &head),
+            (b"loca", &[0, 0, 0, 0, 0, 6, 0, 6]),
+        ]);
+        assert_eq!(
+            glyph_presence(&mut Cursor::new(font)).unwrap(),
+            [false, true, false]
+        );
+
+        let font = woff2_with_table_data(&[
+            (b"glyf", &[0; 12]),
+            (b"head", &head),
+            (b"loca", &[0, 0, 0, 7]),
+        ]);
+        assert!(matches!(
+            glyph_presence(&mut Cursor::new(font)),
+            Err(DecodeError::Invalid(_))
+        ));
+        assert!(matches!(
+            glyph_presence(&mut Cursor::new(TEST_KERN_ONE_CFF)),
+            Err(DecodeError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn decode_sample_font_tables() {
+        use super::{decode_tables, list_tables};
+        use crate::sfnt::finalize_sfnt;
+
+        for font in [
+            LATO_V22_LATIN_REGULAR,
+            FONTAWESOME_REGULAR_400,
+            TEST_KERN_ONE_CFF,
+        ] {
+            let tables = decode_tables(&mut Cursor::new(font)).unwrap();
+            let tags: Vec<_> = tables.iter().map(|(tag, _)| *tag).collect();
+            assert_eq!(tags, list_tables(&mut Cursor::new(font)).unwrap());
+
+            let ttf = convert_woff2_to_ttf(&mut Cursor::new(font)).unwrap();
+            let face = ttf_parser::RawFace::from_slice(&ttf, 0).unwrap();
+            for (tag, data) in &tables {
+                let mut expected = face
+                    .table(ttf_parser::Tag::from_bytes(&tag.0))
+                    .unwrap()
+                    .to_vec();
+                if &tag.0 == b"head" {
+                    expected[8..12].fill(0);
+                }
+                assert_eq!(data, &expected);
+            }
+            if font == TEST_KERN_ONE_CFF {
+                let flavor = four_cc::FourCC(ttf[..4].try_into().unwrap());
+                assert_eq!(ttf, finalize_sfnt(tables, flavor));
+            }
+        }
+    }
+
+    /// Appends `block` to `font` at the next multiple of four bytes and updates the length in the
+    /// header, returning the offset of the block.
+    fn append_block(font: &mut Vec<u8>, block: &[u8]) -> u32 {
+        font.resize((font.len() + 3) & !3, 0);
+        let offset = font.len() as u32;
+        font.extend_from_slice(block);
+        let length = font.len() as u32;
+        font[8..12].copy_from_slice(&length.to_be_bytes());
+        offset
+    }
+
+    /// Appends a metadata block holding `xml` to `font`.
+    fn add_metadata(font: &mut Vec<u8>, xml: &[u8]) {
+        let block = stored_brotli_stream(xml);
+        let meta_offset = append_block(font, &block);
+        font[28..32].copy_from_slice(&meta_offset.to_be_bytes());
+        font[32..36].copy_from_slice(&(block.len() as u32).to_be_bytes());
+        font[36..40].copy_from_slice(&(xml.len() as u32).to_be_bytes());
+    }
+
+    /// Appends a private data block holding `data` to `font`.
+    fn add_private_data(font: &mut Vec<u8>, data: &[u8]) {
+        let private_offset = append_block(font, data);
+        font[40..44].copy_from_slice(&private_offset.to_be_bytes());
+        font[44..48].copy_from_slice(&(data.len() as u32).to_be_bytes());
+    }
+
+    #[test]
+    fn extract_font_metadata() {
+        use super::extract_metadata;
+
+        assert_eq!(
+            extract_metadata(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap(),
+            None
+        );
+
+        let xml = br#"<?xml version="1.0" encoding="UTF-8"?><metadata version="1.0"/>"#;
+        let mut font = LATO_V22_LATIN_REGULAR.to_vec();
+        add_metadata(&mut font, xml);
+        assert_eq!(
+            extract_metadata(&mut Cursor::new(&font)).unwrap().unwrap(),
+            xml
+        );
+        // the font itself is still fine
+        convert_woff2_to_ttf(&mut Cursor::new(&font)).unwrap();
+
+        let mut out_of_bounds = font.clone();
+        out_of_bounds[28..32].copy_from_slice(&(font.len() as u32 - 4).to_be_bytes());
+        assert!(matches!(
+            extract_metadata(&mut Cursor::new(out_of_bounds)),
+            Err(DecodeError::Invalid(_))
+        ));
+        let mut wrong_length = font;
+        wrong_length[39] += 1;
+        assert!(matches!(
+            extract_metadata(&mut Cursor::new(wrong_length)),
+            Err(DecodeError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn extract_private_data_block() {
+        use super::{extract_metadata, extract_private_data};
+
+        assert_eq!(
+            extract_private_data(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap(),
+            None
+        );
+
+        let xml = br#"<?xml version="1.0" encoding="UTF-8"?><metadata version="1.0"/>"#;
+        // a valid brotli stream, which must come back as it is rather than decompressed
+        let private_data = stored_brotli_stream(b"private");
+        let mut metadata_first = LATO_V22_LATIN_REGULAR.to_vec();
+        add_metadata(&mut metadata_first, xml);
+        add_private_data(&mut metadata_first, &private_data);
+        // the other way around isn't what the spec asks for, but both blocks can still be found
+        let mut private_data_first = LATO_V22_LATIN_REGULAR.to_vec();
+        add_private_data(&mut private_data_first, &private_data);
+        add_metadata(&mut private_data_first, xml);
+
+        for font in [metadata_first, private_data_first] {
+            assert_eq!(
+                extract_private_data(&mut Cursor::new(&font))
+                    .unwrap()
+                    .unwrap(),
+                private_data
+            );
+            assert_eq!(
+                extract_metadata(&mut Cursor::new(&font)).unwrap().unwrap(),
+                xml
+            );
+            let strict = DecodeOptions::new().strict(true).build();
+            assert_eq!(
+                convert_woff2_to_ttf_with_options(&mut Cursor::new(&font), &strict).unwrap(),
+                convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap()
+            );
+
+            let mut out_of_bounds = font.clone();
+            out_of_bounds[44..48].copy_from_slice(&(font.len() as u32).to_be_bytes());
+            assert!(matches!(
+                extract_private_data(&mut Cursor::new(out_of_bounds)),
+                Err(DecodeError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn convert_to_sinks() {
+        use std::io::Write;
+
+        use super::{convert_woff2, convert_woff2_to_ttf_into, WriteSink};
+
+        let options = DecodeOptions::default();
+        let expected = convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+
+        let mut written = b"existing".to_vec();
+        convert_woff2(
+            &mut Cursor::new(LATO_V22_LATIN_REGULAR),
+            &options,
+            WriteSink::new(&mut written),
+        )
+        .unwrap();
+        assert_eq!(&written[..8], b"existing");
+        assert_eq!(written[8..], expected);
+
+        // a borrowed `Vec` is replaced rather than appended to
+        convert_woff2(
+            &mut Cursor::new(LATO_V22_LATIN_REGULAR),
+            &options,
+            &mut written,
+        )
+        .unwrap();
+        assert_eq!(written, expected);
+
+        // the buffer's allocation is reused
+        let mut out_buffer = Vec::with_capacity(2 * expected.len());
+        convert_woff2_to_ttf_into(&mut Cursor::new(LATO_V22_LATIN_REGULAR), &mut out_buffer)
+            .unwrap();
+        assert_eq!(out_buffer, expected);
+        assert!(out_buffer.capacity() >= 2 * expected.len());
+
+        struct FailingWriter;
+        impl Write for FailingWriter {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let error = convert_woff2(
+            &mut Cursor::new(LATO_V22_LATIN_REGULAR),
+            &options,
+            WriteSink::new(FailingWriter),
+        )
+        .unwrap_err();
+        assert!(
+            matches!(&error, DecodeError::Write(error) if error.kind() == std::io::ErrorKind::BrokenPipe)
+        );
+        assert!(!error.is_corrupt());
+    }
+
+    #[test]
+    fn font_info() {
+        use four_cc::FourCC;
+
+        let (ttf, info) = convert_woff2_to_ttf_with_header(
+            &mut Cursor::new(LATO_V22_LATIN_REGULAR),
+            &DecodeOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(ttf_parser::fonts_in_collection(&ttf), None);
+        assert_eq!(info.flavor, FourCC([0, 1, 0, 0]));
+        assert_eq!(info.num_fonts, 1);
+        assert!(!info.is_collection());
+        assert_eq!((info.major_version, info.minor_version), (1, 0));
+
+        let (_, info) = convert_woff2_to_ttf_with_header(
+            &mut Cursor::new(FONTAWESOME_REGULAR_400),
+            &DecodeOptions::default(),
+        )
+        .unwrap();
+        assert_eq!((info.major_version, info.minor_version), (331, 34340));
+    }
+
+    #[test]
+    fn table_order_is_preserved() {
+        use four_cc::FourCC;
+
+        let (ttf, table_order) = convert_woff2_to_ttf_with_table_order(
+            &mut Cursor::new(LATO_V22_LATIN_REGULAR),
+            &DecodeOptions::default(),
+        )
+        .unwrap();
+        let directory =
+            crate::decode::parse_table_directory(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        let woff2_order: Vec<_> = directory.tables.iter().map(|table| table.tag).collect();
+        assert_eq!(table_order, woff2_order);
+
+        // the output directory is still sorted
+        let output_order: Vec<_> = ttf[12..]
+            .chunks_exact(16)
+            .take(table_order.len())
+            .map(|record| FourCC(record[..4].try_into().unwrap()))
+            .collect();
+        let mut sorted_order = table_order.clone();
+        sorted_order.sort_unstable_by_key(|tag| tag.0);
+        assert_ne!(table_order, sorted_order);
+        assert_eq!(output_order, sorted_order);
+    }
+
+    /// Returns a copy of `font` with its length changed to `new_length`, updating the header to
+    /// match.
+    fn resize_font(font: &[u8], new_length: usize) -> Vec<u8> {
+        let mut font = font.to_vec();
+        font.resize(new_length, 0);
+        font[8..12].copy_from_slice(&(new_length as u32).to_be_bytes());
+        font
+    }
+
+    #[test]
+    fn read_font_without_padding() {
+        // Lato has a single byte of padding after the compressed stream, which isn't needed at the
+        // end of the file
+        let font = resize_font(LATO_V22_LATIN_REGULAR, LATO_V22_LATIN_REGULAR.len() - 1);
+        let ttf = convert_woff2_to_ttf(&mut Cursor::new(font)).unwrap();
+        let _parsed_ttf = ttf_parser::Face::from_slice(&ttf, 0).unwrap();
+    }
+
+    #[test]
+    fn reject_trailing_data() {
+        let mut font = resize_font(LATO_V22_LATIN_REGULAR, LATO_V22_LATIN_REGULAR.len() + 4);
+        let len = font.len();
+        font[len - 4..].copy_from_slice(b"wOF2");
+        assert!(matches!(
+            convert_woff2_to_ttf(&mut Cursor::new(font)),
+            Err(DecodeError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn reject_nonzero_padding() {
+        let mut font = LATO_V22_LATIN_REGULAR.to_vec();
+        *font.last_mut().unwrap() = 1;
+        assert!(matches!(
+            convert_woff2_to_ttf(&mut Cursor::new(font)),
+            Err(DecodeError::Invalid(_))
+        ));
+    }
+
+    /// Returns a copy of Lato with `tail` added to the end of its compressed stream, which is
+    /// followed by one byte of padding, and counted in the stream's size.
+    fn lato_with_stream_tail(tail: &[u8]) -> Vec<u8> {
+        let stream_end = LATO_V22_LATIN_REGULAR.len() - 1;
+        let mut font = LATO_V22_LATIN_REGULAR[..stream_end].to_vec();
+        font.extend_from_slice(tail);
+        let compressed_size = u32::from_be_bytes(font[20..24].try_into().unwrap());
+        font[20..24].copy_from_slice(&(compressed_size + tail.len() as u32).to_be_bytes());
+        let length = (font.len() + 3) & !3;
+        resize_font(&font, length)
+    }
+
+    #[test]
+    fn reject_data_inside_compressed_stream() {
+        use bytes::Bytes;
+
+        use super::convert_woff2_bytes_to_ttf;
+
+        fn is_unexpected_data(result: Result<Vec<u8>, DecodeError>) -> bool {
+            let message = match result {
+                Err(DecodeError::Invalid(message)) => message,
+                _ => return false,
+            };
+            message == "Unexpected data after compressed stream"
+        }
+
+        for tail in [&b"JUNKJ"[..], b"\0\0\0\0", b"\0\0\x01"] {
+            let font = lato_with_stream_tail(tail);
+            let result = convert_woff2_to_ttf(&mut Cursor::new(&font));
+            assert!(is_unexpected_data(result));
+            let result = convert_woff2_bytes_to_ttf(Bytes::from(font.clone()));
+            assert!(is_unexpected_data(result));
+            #[cfg(feature = "streaming")]
+            {
+                let result = super::convert_woff2_to_ttf_streaming(
+                    &mut Cursor::new(&font),
+                    &DecodeOptions::default(),
+                );
+                assert!(is_unexpected_data(result));
+            }
+        }
+
+        // a few bytes of zero padding are fine
+        let expected = convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        let font = lato_with_stream_tail(&[0; 3]);
+        assert_eq!(
+            convert_woff2_to_ttf(&mut Cursor::new(&font)).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn reject_truncated_stream() {
+        let font = resize_font(LATO_V22_LATIN_REGULAR, LATO_V22_LATIN_REGULAR.len() - 100);
+        assert!(matches!(
+            convert_woff2_to_ttf(&mut Cursor::new(font)),
+            Err(DecodeError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn custom_brotli_backend() {
+        use std::{
+            io::{Read, Write},
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc,
+            },
+        };
+
+        use crate::brotli_backend::{BrotliBackend, DefaultBrotliBackend};
+
+        #[derive(Default)]
+        struct CountingBackend {
+            calls: AtomicUsize,
+        }
+
+        impl BrotliBackend for CountingBackend {
+            fn decompress(
+                &self,
+                input: &mut dyn Read,
+                output: &mut dyn Write,
+            ) -> std::io::Result<()> {
+                self.calls.fetch_add(1, Ordering::Relaxed);
+                DefaultBrotliBackend.decompress(input, output)
+            }
+        }
+
+        let backend = Arc::new(CountingBackend::default());
+        let options = DecodeOptions::new().brotli_backend(backend.clone()).build();
+        let ttf =
+            convert_woff2_to_ttf_with_options(&mut Cursor::new(LATO_V22_LATIN_REGULAR), &options)
+                .unwrap();
+        assert_eq!(
+            ttf,
+            convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap()
+        );
+        assert_eq!(backend.calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn failing_brotli_backend() {
+        use std::{
+            io::{Read, Write},
+            sync::Arc,
+        };
+
+        use crate::brotli_backend::BrotliBackend;
+
+        struct FailingBackend;
+
+        impl BrotliBackend for FailingBackend {
+            fn decompress(&self, _: &mut dyn Read, _: &mut dyn Write) -> std::io::Result<()> {
+                Err(std::io::Error::other("no brotli here"))
+            }
+        }
+
+        let options = DecodeOptions::new()
+            .brotli_backend(Arc::new(FailingBackend))
+            .build();
+        assert!(matches!(
+            convert_woff2_to_ttf_with_options(&mut Cursor::new(LATO_V22_LATIN_REGULAR), &options),
+            Err(DecodeError::Decompression(_))
+        ));
+    }
+
+    #[test]
+    fn reject_num_tables_larger_than_file() {
+        let mut font = LATO_V22_LATIN_REGULAR[..58].to_vec();
+        font[12..14].copy_from_slice(&u16::MAX.to_be_bytes());
+        assert!(matches!(
+            convert_woff2_to_ttf(&mut Cursor::new(font)),
+            Err(DecodeError::TableDirectory(_))
+        ));
+    }
+
+    #[test]
+    fn reject_short_decompressed_stream() {
+        use std::{
+            io::{Read, Write},
+            sync::Arc,
+        };
+
+        use crate::brotli_backend::{BrotliBackend, DefaultBrotliBackend};
+
+        struct TruncatingBackend;
+
+        impl BrotliBackend for TruncatingBackend {
+            fn decompress(
+                &self,
+                input: &mut dyn Read,
+                output: &mut dyn Write,
+            ) -> std::io::Result<()> {
+                let mut tables = Vec::new();
+                DefaultBrotliBackend.decompress(input, &mut tables)?;
+                tables.pop();
+                output.write_all(&tables)
+            }
+        }
+
+        let options = DecodeOptions::new()
+            .brotli_backend(Arc::new(TruncatingBackend))
+            .build();
+        let result =
+            convert_woff2_to_ttf_with_options(&mut Cursor::new(LATO_V22_LATIN_REGULAR), &options);
+        assert!(
+            matches!(result, Err(DecodeError::Invalid(message)) if message == "decompressed size mismatch")
+        );
+    }
+
+    #[cfg(feature = "streaming")]
+    #[test]
+    fn streaming_matches_regular_decode() {
+        use super::convert_woff2_to_ttf_streaming;
+
+        let options = DecodeOptions::new().strict(true).build();
+        for font in [
+            LATO_V22_LATIN_REGULAR,
+            LATO_V22_LATIN_REGULAR_HMTX_TRANSFORMED,
+            TEST_KERN_ONE_CFF,
+        ] {
+            let mut buffer = Cursor::new(font);
+            let ttf = convert_woff2_to_ttf_streaming(&mut buffer, &options).unwrap();
+            assert_eq!(ttf, convert_woff2_to_ttf(&mut Cursor::new(font)).unwrap());
+            assert_eq!(buffer.position() as usize, font.len());
+        }
+
+        let without_checksums = DecodeOptions::new().compute_checksums(false).build();
+        assert_eq!(
+            convert_woff2_to_ttf_streaming(
+                &mut Cursor::new(LATO_V22_LATIN_REGULAR),
+                &without_checksums
+            )
+            .unwrap(),
+            convert_woff2_to_ttf_with_options(
+                &mut Cursor::new(LATO_V22_LATIN_REGULAR),
+                &without_checksums
+            )
+            .unwrap()
+        );
+
+        // `loca` comes after `hmtx` here, so it's written in a different place
+        let ttf =
+            convert_woff2_to_ttf_streaming(&mut Cursor::new(FONTAWESOME_REGULAR_400), &options)
+                .unwrap();
+        let expected = convert_woff2_to_ttf(&mut Cursor::new(FONTAWESOME_REGULAR_400)).unwrap();
+        assert_eq!(ttf.len(), expected.len());
+        assert_eq!(crate::checksum::calculate_checksum(&ttf), 0xB1B0AFBA);
+        let face = ttf_parser::RawFace::from_slice(&ttf, 0).unwrap();
+        let expected_face = ttf_parser::RawFace::from_slice(&expected, 0).unwrap();
+        for tag in [b"cmap", b"glyf", b"loca", b"hmtx", b"post"] {
+            let tag = ttf_parser::Tag::from_bytes(tag);
+            assert_eq!(face.table(tag), expected_face.table(tag));
+        }
+        // but it's the same as the regular decoder's in physical order
+        let physical = DecodeOptions::new().physical_table_order(true).build();
+        assert_eq!(
+            ttf,
+            convert_woff2_to_ttf_with_options(&mut Cursor::new(FONTAWESOME_REGULAR_400), &physical)
+                .unwrap()
+        );
+    }
+
+    #[cfg(feature = "backend-brotli")]
+    #[test]
+    fn stop_decompression_bomb() {
+        use std::{
+            io::{Read, Write},
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc,
+            },
+        };
+
+        use crate::{
+            brotli_backend::{BrotliBackend, DefaultBrotliBackend},
+            test_resources::woff2_with_table_data,
+        };
+
+        /// Counts the bytes the default backend tries to write.
+        #[derive(Default)]
+        struct CountingBackend {
+            written: AtomicUsize,
+        }
+
+        struct CountingWriter<'a, W> {
+            writer: W,
+            written: &'a AtomicUsize,
+        }
+
+        impl<W: Write> Write for CountingWriter<'_, W> {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.written.fetch_add(buf.len(), Ordering::Relaxed);
+                self.writer.write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                self.writer.flush()
+            }
+        }
+
+        impl BrotliBackend for CountingBackend {
+            fn decompress(
+                &self,
+                input: &mut dyn Read,
+                output: &mut dyn Write,
+            ) -> std::io::Result<()> {
+                let mut output = CountingWriter {
+                    writer: output,
+                    written: &self.written,
+                };
+                DefaultBrotliBackend.decompress(input, &mut output)
+            }
+        }
+
+        // a single four byte table, but a stream of 64 MiB of zeros
+        let mut bomb = Vec::new();
+        let params = brotli::enc::BrotliEncoderParams {
+            quality: 1,
+            ..Default::default()
+        };
+        brotli::BrotliCompress(&mut &vec![0; 64 << 20][..], &mut bomb, &params).unwrap();
+        let mut font = woff2_with_table_data(&[(b"head", &[0; 4])]);
+        // the header and a table directory entry of flags, tag and length
+        let stream_start = 48 + 6;
+        font.truncate(stream_start);
+        font.extend_from_slice(&bomb);
+        font[20..24].copy_from_slice(&(bomb.len() as u32).to_be_bytes());
+        let font = resize_font(&font, (font.len() + 3) & !3);
+
+        let backend = Arc::new(CountingBackend::default());
+        let options = DecodeOptions::new().brotli_backend(backend.clone()).build();
+        assert!(matches!(
+            convert_woff2_to_ttf_with_options(&mut Cursor::new(font), &options),
+            Err(DecodeError::Invalid(message)) if message == "decompressed size mismatch"
+        ));
+        // stopped at the first write, which is at most the default backend's buffer size
+        assert!(backend.written.load(Ordering::Relaxed) <= 4096);
+    }
+
+    #[cfg(feature = "streaming")]
+    #[test]
+    fn streaming_rejects_bad_streams() {
+        use std::{
+            io::{Read, Write},
+            sync::Arc,
+        };
+
+        use super::convert_woff2_to_ttf_streaming;
+        use crate::brotli_backend::{BrotliBackend, DefaultBrotliBackend};
+
+        /// Adds or removes a byte at the end of the decompressed data.
+        struct ResizingBackend(bool);
+
+        impl BrotliBackend for ResizingBackend {
+            fn decompress(
+                &self,
+                input: &mut dyn Read,
+                output: &mut dyn Write,
+            ) -> std::io::Result<()> {
+                let mut tables = Vec::new();
+                DefaultBrotliBackend.decompress(input, &mut tables)?;
+                if self.0 {
+                    tables.push(0);
+                } else {
+                    tables.pop();
+                }
+                output.write_all(&tables)
+            }
+        }
+
+        let options = DecodeOptions::new()
+            .brotli_backend(Arc::new(ResizingBackend(false)))
+            .build();
+        assert!(matches!(
+            convert_woff2_to_ttf_streaming(&mut Cursor::new(LATO_V22_LATIN_REGULAR), &options),
+            Err(DecodeError::Decompression(_))
+        ));
+        let options = DecodeOptions::new()
+            .brotli_backend(Arc::new(ResizingBackend(true)))
+            .build();
+        assert!(matches!(
+            convert_woff2_to_ttf_streaming(&mut Cursor::new(LATO_V22_LATIN_REGULAR), &options),
+            Err(DecodeError::Invalid(message)) if message == "decompressed size mismatch"
+        ));
+
+        // cut off in the middle of the compressed stream
+        let mut font = LATO_V22_LATIN_REGULAR.to_vec();
+        let compressed_size = u32::from_be_bytes(font[20..24].try_into().unwrap()) as usize;
+        let cut = font.len() - compressed_size / 2;
+        font[cut..].fill(0);
+        assert!(
+            convert_woff2_to_ttf_streaming(&mut Cursor::new(font), &DecodeOptions::default())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn error_source_chain() {
+        use std::{
+            error::Error,
+            io::{Read, Write},
+            sync::Arc,
+        };
+
+        use crate::{
+            brotli_backend::{BrotliBackend, DefaultBrotliBackend},
+            glyf_decoder::GlyfDecoderError,
+            woff2::table_directory::{WriteTablesError, GLYF_TAG},
+        };
+
+        /// Corrupts the index format in the transformed `glyf` table.
+        struct CorruptingBackend {
+            glyf_offset: usize,
+        }
+
+        impl BrotliBackend for CorruptingBackend {
+            fn decompress(
+                &self,
+                input: &mut dyn Read,
+                output: &mut dyn Write,
+            ) -> std::io::Result<()> {
+                let mut tables = Vec::new();
+                DefaultBrotliBackend.decompress(input, &mut tables)?;
+                tables[self.glyf_offset + 6..][..2].copy_from_slice(&2u16.to_be_bytes());
+                output.write_all(&tables)
+            }
+        }
+
+        let directory =
+            crate::decode::parse_table_directory(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        let glyf = directory.tables.iter().find(|t| t.tag == GLYF_TAG).unwrap();
+        let options = DecodeOptions::new()
+            .brotli_backend(Arc::new(CorruptingBackend {
+                glyf_offset: glyf.src_offset as usize,
+            }))
+            .build();
+        let error =
+            convert_woff2_to_ttf_with_options(&mut Cursor::new(LATO_V22_LATIN_REGULAR), &options)
+                .unwrap_err();
+        assert!(matches!(error, DecodeError::Tables(_)));
+        let source = error.source().unwrap();
+        assert!(matches!(
+            source.downcast_ref::<WriteTablesError>(),
+            Some(WriteTablesError::GlyfDecoderError(_))
+        ));
+        let source = source.source().unwrap();
+        assert!(matches!(
+            source.downcast_ref::<GlyfDecoderError>(),
+            Some(GlyfDecoderError::InvalidIndexFormat(2))
+        ));
+    }
+
+    #[test]
+    fn reject_font_larger_than_limit() {
+        let options = DecodeOptions::new().max_decompressed_size(1024).build();
+        assert!(matches!(
+            convert_woff2_to_ttf_with_options(&mut Cursor::new(LATO_V22_LATIN_REGULAR), &options),
+            Err(DecodeError::TooLarge { limit: 1024, .. })
+        ));
+    }
+
+    #[test]
+    fn builder_defaults() {
+        let options = DecodeOptions::new().build();
+        assert_eq!(
+            DecodeOptions::default().max_decompressed_size,
+            options.max_decompressed_size
+        );
+        assert!(options.compute_checksums);
+        let options = DecodeOptions::new().compute_checksums(false).build();
+        assert!(!options.compute_checksums);
+        convert_woff2_to_ttf_with_options(&mut Cursor::new(LATO_V22_LATIN_REGULAR), &options)
+            .unwrap();
     }
 
     #[test]
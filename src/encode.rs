@@ -0,0 +1,419 @@
+//! Interface for encoding WOFF2 files
+//!
+//! Tables are stored without transformations, so encoding is mostly a matter of compressing the
+//! table data. This needs the `backend-brotli` feature.
+
+use bytes::BufMut;
+use four_cc::FourCC;
+use thiserror::Error;
+
+use crate::{
+    buffer_util::{pad_to_multiple_of_four, BufMutExt, SafeBuf, TruncatedError},
+    magic_numbers::{TTF_CFF_FLAVOR, TTF_COLLECTION_FLAVOR, TTF_TRUE_TYPE_FLAVOR, WOFF2_SIGNATURE},
+    ttf_header::{calculate_header_size, TableRecord},
+    woff2::{
+        header::HEADER_SIZE,
+        table_directory::{GLYF_TAG, KNOWN_TABLE_TAGS, LOCA_TAG},
+    },
+};
+
+#[derive(Error, Debug)]
+pub enum EncodeError {
+    #[error("Invalid font file {0}")]
+    Invalid(String),
+    #[error("Unsupported feature {0}")]
+    Unsupported(&'static str),
+    #[error("Failed to compress font data")]
+    Compression(#[source] std::io::Error),
+}
+
+impl From<TruncatedError> for EncodeError {
+    fn from(_: TruncatedError) -> Self {
+        EncodeError::Invalid("Truncated table directory".to_string())
+    }
+}
+
+/// Transformation version 3, which is the null transform for `glyf` and `loca`
+const GLYF_LOCA_NULL_TRANSFORM: u8 = 0xC0;
+
+/// Options controlling how WOFF2 files are encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EncodeOptions {
+    /// The brotli quality, from 1 (fastest) to 11 (smallest); higher values are treated as 11. 0
+    /// stores the table data without compressing it, which is useful for debugging and for data
+    /// that doesn't compress.
+    pub compression_quality: u8,
+    /// The base 2 logarithm of the brotli window size, from 10 to 24. Values outside that range are
+    /// clamped, since the WOFF2 spec doesn't allow windows larger than 24 bits.
+    pub brotli_window_bits: u8,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        EncodeOptions {
+            compression_quality: 11,
+            brotli_window_bits: 22,
+        }
+    }
+}
+
+/// The smallest window brotli supports
+const MIN_BROTLI_WINDOW_BITS: u8 = 10;
+/// The largest window allowed in WOFF2 files
+const MAX_BROTLI_WINDOW_BITS: u8 = 24;
+
+/// Converts an OpenType font (TrueType or CFF flavored) into a WOFF2 font.
+pub fn convert_ttf_to_woff2(input: &[u8]) -> Result<Vec<u8>, EncodeError> {
+    convert_ttf_to_woff2_with_options(input, &EncodeOptions::default())
+}
+
+/// Converts an OpenType font (TrueType or CFF flavored) into a WOFF2 font, using the given
+/// options.
+pub fn convert_ttf_to_woff2_with_options(
+    input: &[u8],
+    options: &EncodeOptions,
+) -> Result<Vec<u8>, EncodeError> {
+    let mut input_buffer = input;
+    let flavor = FourCC(input_buffer.try_get_u32()?.to_be_bytes());
+    match flavor {
+        TTF_TRUE_TYPE_FLAVOR | TTF_CFF_FLAVOR => {}
+        TTF_COLLECTION_FLAVOR => return Err(EncodeError::Unsupported("font collections")),
+        _ => return Err(EncodeError::Invalid("Invalid font flavor".to_string())),
+    }
+    let num_tables = input_buffer.try_get_u16()?;
+    // search_range, entry_selector, range_shift
+    input_buffer.try_get_u16()?;
+    input_buffer.try_get_u32()?;
+    let mut tables = (0..num_tables)
+        .map(|_| TableRecord::from_buf(&mut input_buffer))
+        .collect::<Result<Vec<_>, _>>()?;
+    if tables
+        .iter()
+        .any(|table| input.get(table.get_range()).is_none())
+    {
+        return Err(EncodeError::Invalid("Table out of bounds".to_string()));
+    }
+    // tables can overlap, so this can be much bigger than the input
+    let total_sfnt_size = calculate_header_size(tables.len())
+        + tables
+            .iter()
+            .map(|table| (table.length as usize + 3) & !3)
+            .sum::<usize>();
+    let total_sfnt_size = u32::try_from(total_sfnt_size).map_err(|_| font_too_large())?;
+
+    // the decoder needs `loca` to follow `glyf`
+    tables.sort_unstable_by_key(|table| match table.tag {
+        LOCA_TAG => (GLYF_TAG.0, 1),
+        tag => (tag.0, 0),
+    });
+
+    let mut table_directory = Vec::new();
+    let mut table_data = Vec::new();
+    for table in &tables {
+        let version = match table.tag {
+            GLYF_TAG | LOCA_TAG => GLYF_LOCA_NULL_TRANSFORM,
+            _ => 0,
+        };
+        match KNOWN_TABLE_TAGS.iter().position(|&tag| tag == table.tag) {
+            Some(index) => table_directory.put_u8(version | index as u8),
+            None => {
+                table_directory.put_u8(version | 0x3F);
+                table_directory.put_slice(&table.tag.0);
+            }
+        }
+        table_directory.put_base_128(table.length);
+        table_data.extend_from_slice(&input[table.get_range()]);
+    }
+
+    let compressed = if options.compression_quality == 0 {
+        store_uncompressed(&table_data)
+    } else {
+        let mut compressed = Vec::new();
+        let params = brotli::enc::BrotliEncoderParams {
+            quality: options.compression_quality.min(11).into(),
+            lgwin: options
+                .brotli_window_bits
+                .clamp(MIN_BROTLI_WINDOW_BITS, MAX_BROTLI_WINDOW_BITS)
+                .into(),
+            mode: brotli::enc::backward_references::BrotliEncoderMode::BROTLI_MODE_FONT,
+            size_hint: table_data.len(),
+            ..Default::default()
+        };
+        brotli::BrotliCompress(&mut &table_data[..], &mut compressed, &params)
+            .map_err(EncodeError::Compression)?;
+        compressed
+    };
+
+    let mut output = Vec::with_capacity(HEADER_SIZE + table_directory.len() + compressed.len());
+    output.put_slice(&WOFF2_SIGNATURE.0);
+    output.put_slice(&flavor.0);
+    // length, filled in below
+    output.put_u32(0);
+    output.put_u16(num_tables);
+    // reserved
+    output.put_u16(0);
+    output.put_u32(total_sfnt_size);
+    output.put_u32(u32::try_from(compressed.len()).map_err(|_| font_too_large())?);
+    // major and minor version, then the (empty) metadata and private data blocks
+    output.resize(HEADER_SIZE, 0);
+    output.put_slice(&table_directory);
+    output.put_slice(&compressed);
+    pad_to_multiple_of_four(&mut output);
+    let length = u32::try_from(output.len()).map_err(|_| font_too_large())?;
+    output[8..12].copy_from_slice(&length.to_be_bytes());
+    Ok(output)
+}
+
+/// The error for a font whose sizes don't fit in the 32-bit fields of the WOFF2 header.
+fn font_too_large() -> EncodeError {
+    EncodeError::Invalid("Font too large".to_string())
+}
+
+/// Writes `data` as a brotli stream made of uncompressed meta-blocks.
+///
+/// Spec: https://www.rfc-editor.org/rfc/rfc7932#section-9.2
+fn store_uncompressed(data: &[u8]) -> Vec<u8> {
+    // the largest length that fits in four nibbles
+    const MAX_META_BLOCK_LENGTH: usize = 1 << 16;
+    let mut output = Vec::with_capacity(data.len() + data.len() / MAX_META_BLOCK_LENGTH * 3 + 4);
+    let mut bits = BitWriter::default();
+    // WBITS = 16, which doesn't matter as nothing is copied from the window
+    bits.put_bits(&mut output, 0, 1);
+    for chunk in data.chunks(MAX_META_BLOCK_LENGTH) {
+        // ISLAST = 0, MNIBBLES = 4
+        bits.put_bits(&mut output, 0, 1);
+        bits.put_bits(&mut output, 0, 2);
+        bits.put_bits(&mut output, chunk.len() as u32 - 1, 16);
+        // ISUNCOMPRESSED = 1, then the data starting at the next byte boundary
+        bits.put_bits(&mut output, 1, 1);
+        bits.flush(&mut output);
+        output.put_slice(chunk);
+    }
+    // ISLAST = 1, ISLASTEMPTY = 1
+    bits.put_bits(&mut output, 0b11, 2);
+    bits.flush(&mut output);
+    output
+}
+
+/// Packs bits into bytes least significant bit first, as brotli streams are read.
+#[derive(Default)]
+struct BitWriter {
+    value: u32,
+    num_bits: u32,
+}
+
+impl BitWriter {
+    fn put_bits(&mut self, output: &mut Vec<u8>, value: u32, num_bits: u32) {
+        debug_assert!(num_bits <= 16 && value >> num_bits == 0);
+        self.value |= value << self.num_bits;
+        self.num_bits += num_bits;
+        while self.num_bits >= 8 {
+            output.put_u8(self.value as u8);
+            self.value >>= 8;
+            self.num_bits -= 8;
+        }
+    }
+
+    /// Writes out any partial byte, padded with zeros.
+    fn flush(&mut self, output: &mut Vec<u8>) {
+        if self.num_bits > 0 {
+            output.put_u8(self.value as u8);
+        }
+        self.value = 0;
+        self.num_bits = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use ttf_parser::{Face, GlyphId, OutlineBuilder, RawFace, Rect, Tag};
+
+    use super::{
+        convert_ttf_to_woff2, convert_ttf_to_woff2_with_options, store_uncompressed, EncodeError,
+        EncodeOptions,
+    };
+    use crate::{
+        decode::{convert_woff2_to_ttf, parse_table_directory},
+        test_resources::{FONTAWESOME_REGULAR_400, LATO_V22_LATIN_REGULAR, TEST_KERN_ONE_CFF},
+    };
+
+    /// Records outlines so that they can be compared.
+    #[derive(Default, PartialEq, Debug)]
+    struct Outline(Vec<(char, Vec<f32>)>);
+
+    impl OutlineBuilder for Outline {
+        fn move_to(&mut self, x: f32, y: f32) {
+            self.0.push(('M', vec![x, y]));
+        }
+        fn line_to(&mut self, x: f32, y: f32) {
+            self.0.push(('L', vec![x, y]));
+        }
+        fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+            self.0.push(('Q', vec![x1, y1, x, y]));
+        }
+        fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+            self.0.push(('C', vec![x1, y1, x2, y2, x, y]));
+        }
+        fn close(&mut self) {
+            self.0.push(('Z', vec![]));
+        }
+    }
+
+    fn glyph(face: &Face, glyph_id: GlyphId) -> (Outline, Option<Rect>, Option<u16>) {
+        let mut outline = Outline::default();
+        let bbox = face.outline_glyph(glyph_id, &mut outline);
+        (outline, bbox, face.glyph_hor_advance(glyph_id))
+    }
+
+    /// Encodes `ttf`, checks that it got smaller, and checks that decoding it again gives the same
+    /// glyphs.
+    fn assert_round_trip(ttf: &[u8]) {
+        let woff2 = convert_ttf_to_woff2(ttf).unwrap();
+        assert!(woff2.len() < ttf.len());
+        assert_same_glyphs(ttf, woff2);
+    }
+
+    /// Checks that decoding `woff2` gives the same glyphs as in `ttf`.
+    fn assert_same_glyphs(ttf: &[u8], woff2: Vec<u8>) {
+        let decoded = convert_woff2_to_ttf(&mut Cursor::new(woff2)).unwrap();
+
+        let original = Face::from_slice(ttf, 0).unwrap();
+        let round_tripped = Face::from_slice(&decoded, 0).unwrap();
+        assert_eq!(
+            original.number_of_glyphs(),
+            round_tripped.number_of_glyphs()
+        );
+        for glyph_id in 0..original.number_of_glyphs() {
+            let glyph_id = GlyphId(glyph_id);
+            assert_eq!(
+                glyph(&original, glyph_id),
+                glyph(&round_tripped, glyph_id),
+                "glyph {}",
+                glyph_id.0
+            );
+        }
+    }
+
+    #[test]
+    fn round_trip_sample_fonts() {
+        for font in [
+            LATO_V22_LATIN_REGULAR,
+            FONTAWESOME_REGULAR_400,
+            TEST_KERN_ONE_CFF,
+        ] {
+            let ttf = convert_woff2_to_ttf(&mut Cursor::new(font)).unwrap();
+            assert_round_trip(&ttf);
+        }
+    }
+
+    #[test]
+    fn store_without_compression() {
+        let ttf = convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        let options = EncodeOptions {
+            compression_quality: 0,
+            ..Default::default()
+        };
+        let woff2 = convert_ttf_to_woff2_with_options(&ttf, &options).unwrap();
+        // the tables can be found as is in the file
+        let head = RawFace::from_slice(&ttf, 0)
+            .unwrap()
+            .table(Tag::from_bytes(b"head"))
+            .unwrap();
+        assert!(woff2.windows(head.len()).any(|window| window == head));
+        assert_same_glyphs(&ttf, woff2);
+    }
+
+    /// Reads the window size from the start of a brotli stream.
+    ///
+    /// Spec: https://www.rfc-editor.org/rfc/rfc7932#section-9.1
+    fn brotli_window_bits(stream: &[u8]) -> u32 {
+        let bits = u16::from_le_bytes([stream[0], stream[1]]) as u32;
+        if bits & 1 == 0 {
+            return 16;
+        }
+        match (bits >> 1) & 0x7 {
+            0 => match (bits >> 4) & 0x7 {
+                0 => 17,
+                n => 8 + n,
+            },
+            n => 17 + n,
+        }
+    }
+
+    #[test]
+    fn window_size_is_within_spec() {
+        let ttf = convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        for requested in [0, 16, 24, 30] {
+            let options = EncodeOptions {
+                brotli_window_bits: requested,
+                ..Default::default()
+            };
+            let woff2 = convert_ttf_to_woff2_with_options(&ttf, &options).unwrap();
+            // the compressed stream directly follows the table directory
+            let mut input = Cursor::new(&woff2);
+            parse_table_directory(&mut input).unwrap();
+            let window_bits = brotli_window_bits(&woff2[input.position() as usize..]);
+            assert_eq!(u32::from(requested.clamp(10, 24)), window_bits);
+            assert_same_glyphs(&ttf, woff2);
+        }
+    }
+
+    #[test]
+    fn stored_brotli_streams_decompress() {
+        for len in [0, 1, 65535, 65536, 65537, 200_000] {
+            let data: Vec<u8> = (0..len).map(|i| (i * 7 % 251) as u8).collect();
+            let stored = store_uncompressed(&data);
+            let mut decompressed = Vec::new();
+            brotli::BrotliDecompress(&mut &stored[..], &mut decompressed).unwrap();
+            assert_eq!(data, decompressed, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn reject_invalid_fonts() {
+        assert!(matches!(
+            convert_ttf_to_woff2(b"ttcf\0\0\0\0"),
+            Err(EncodeError::Unsupported(_))
+        ));
+        assert!(matches!(
+            convert_ttf_to_woff2(b"wOF2\0\0\0\0"),
+            Err(EncodeError::Invalid(_))
+        ));
+        assert!(matches!(
+            convert_ttf_to_woff2(&[0, 1, 0, 0, 0, 1]),
+            Err(EncodeError::Invalid(_))
+        ));
+        // one table record pointing past the end of the file
+        let mut font = vec![0, 1, 0, 0, 0, 1, 0, 16, 0, 0, 0, 0];
+        font.extend_from_slice(b"head");
+        font.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 28, 0, 0, 0, 54]);
+        assert!(matches!(
+            convert_ttf_to_woff2(&font),
+            Err(EncodeError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn reject_fonts_too_large() {
+        // every table is the same 64 KiB, which adds up to more than 4 GiB
+        let num_tables = u16::MAX;
+        let data_offset = 12 + 16 * u32::from(num_tables);
+        let mut font = vec![0, 1, 0, 0];
+        font.extend_from_slice(&num_tables.to_be_bytes());
+        font.extend_from_slice(&[0; 6]);
+        for index in 0..num_tables {
+            // a distinct tag for each table, then a zero checksum
+            font.extend_from_slice(&index.to_be_bytes());
+            font.extend_from_slice(&[0; 6]);
+            font.extend_from_slice(&data_offset.to_be_bytes());
+            font.extend_from_slice(&(1u32 << 16).to_be_bytes());
+        }
+        font.resize(data_offset as usize + (1 << 16), 0);
+        assert!(matches!(
+            convert_ttf_to_woff2(&font),
+            Err(EncodeError::Invalid(message)) if message == "Font too large"
+        ));
+    }
+}
@@ -1,4 +1,17 @@
-use std::io::{Cursor, Write};
+//! Reconstruction of transformed `glyf` and `loca` tables
+//!
+//! Spec: https://www.w3.org/TR/WOFF2/#glyf_table_format
+//!
+//! A transformed `glyf` table is split into streams whose sizes are all given up front in its
+//! header, and each glyph takes a little out of every stream. The streams are read side by side,
+//! so the whole table has to be in memory, and the decoder works on a slice of it.
+//! [`decode_glyf_table_from_reader`] reads the table into a buffer first for callers that only have
+//! a reader. [`GlyfStreamLayout`] gives the byte ranges of the streams without decoding anything.
+
+use std::{
+    io::{Cursor, Read, Write},
+    ops::Range,
+};
 
 use bitvec::{order::Msb0, slice::BitSlice};
 use bytes::{Buf, BufMut};
@@ -16,8 +29,22 @@ pub enum GlyfDecoderError {
     Truncated,
     #[error("Composite glyph without bbox")]
     CompositeGlyphWithoutBbox,
+    #[error("Empty glyph with bbox")]
+    EmptyGlyphWithBbox,
     #[error("Extra Data")]
     ExtraData,
+    #[error("Invalid index format {0}")]
+    InvalidIndexFormat(u16),
+    #[error("Glyph {glyph_index} has more than {limit} points")]
+    TooManyPoints { glyph_index: u16, limit: u16 },
+    #[error("Composite glyph {0} has more than {MAX_COMPONENTS_PER_GLYPH} components")]
+    TooManyComponents(u16),
+    #[error("Invalid composite glyph flags {0:#06x}")]
+    InvalidCompositeFlags(u16),
+    #[error("Failed to write glyph data")]
+    Write(#[source] std::io::Error),
+    #[error("Failed to read the transformed glyf table")]
+    Read(#[source] std::io::Error),
 }
 
 impl From<Truncated> for GlyfDecoderError {
@@ -27,8 +54,8 @@ impl From<Truncated> for GlyfDecoderError {
 }
 
 impl From<std::io::Error> for GlyfDecoderError {
-    fn from(_: std::io::Error) -> Self {
-        GlyfDecoderError::Truncated
+    fn from(error: std::io::Error) -> Self {
+        GlyfDecoderError::Write(error)
     }
 }
 
@@ -44,33 +71,51 @@ struct Woff2GlyfDecoder<'a, T> {
     instruction_stream: Cursor<T>,
     overlap_bitmap: Option<&'a BitSlice<u8, Msb0>>,
     index_format: u16,
+    max_points_per_glyph: u16,
 }
 
-fn bit_stream_byte_length(bit_stream_bit_length: u16) -> u16 {
-    ((bit_stream_bit_length >> 5)
-        + if bit_stream_bit_length % 32 != 0 {
-            1
-        } else {
-            0
-        })
-        << 2
+/// The most points a simple glyph can have by default, which is all a TrueType glyph can address
+pub const DEFAULT_MAX_POINTS_PER_GLYPH: u16 = u16::MAX;
+
+/// The most components a composite glyph can have. Real glyphs have a handful, so this only stops
+/// a crafted glyph that keeps setting MORE_COMPONENTS from going through the whole stream.
+pub const MAX_COMPONENTS_PER_GLYPH: usize = 4096;
+
+/// Converts a coordinate to an `i16`, saturating if it's out of range.
+fn clamp_to_i16(value: i32) -> i16 {
+    value.clamp(i16::MIN.into(), i16::MAX.into()) as i16
 }
 
-impl<'a> Woff2GlyfDecoder<'a, &'a [u8]> {
-    fn has_read_all(&self) -> bool {
-        let _n_contour_stream_remaining = self.n_contour_stream.remaining();
-        let _n_points_stream_reminaing = self.n_points_stream.remaining();
+/// Returns the size in bytes of a bitmap with one bit per glyph, padded to a multiple of four bytes.
+fn bit_stream_byte_length(bit_stream_bit_length: u16) -> usize {
+    // computed in `usize`, since the byte length doesn't have to fit in a `u16`
+    usize::from(bit_stream_bit_length).div_ceil(32) * 4
+}
 
-        self.n_contour_stream.remaining() == 0
-            && self.n_points_stream.remaining() == 0
-            && self.flag_stream.remaining() == 0
-            && self.glyph_stream.remaining() == 0
-            && self.composite_stream.remaining() == 0
-            && self.bbox_stream.remaining() == 0
-            && self.instruction_stream.remaining() == 0
-    }
+/// Where the streams of a transformed `glyf` table are, as byte ranges within the table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlyfStreamLayout {
+    pub num_glyphs: u16,
+    /// The index format of the original `loca` table
+    pub index_format: u16,
+    pub n_contour_stream: Range<usize>,
+    pub n_points_stream: Range<usize>,
+    pub flag_stream: Range<usize>,
+    pub glyph_stream: Range<usize>,
+    pub composite_stream: Range<usize>,
+    /// The bitmap of glyphs with an explicit bounding box, at the start of the bbox stream
+    pub bbox_bitmap: Range<usize>,
+    /// The bounding boxes, after the bitmap
+    pub bbox_stream: Range<usize>,
+    pub instruction_stream: Range<usize>,
+    /// The bitmap of glyphs with the OVERLAP_SIMPLE flag, if there is one
+    pub overlap_bitmap: Option<Range<usize>>,
+}
 
-    fn new(transformed_glyf_table: &'a [u8]) -> Result<Self, GlyfDecoderError> {
+impl GlyfStreamLayout {
+    /// Reads the header of a transformed `glyf` table, checking that the streams fill the rest of
+    /// the table exactly and that the bitmaps have a bit for every glyph.
+    pub fn from_slice(transformed_glyf_table: &[u8]) -> Result<Self, GlyfDecoderError> {
         let mut table_buf = Cursor::new(transformed_glyf_table);
 
         const GLYF_HEADER_SIZE: usize = 36;
@@ -82,13 +127,20 @@ impl<'a> Woff2GlyfDecoder<'a, &'a [u8]> {
         let num_glyphs = table_buf.get_u16();
         let bitmap_stream_length = bit_stream_byte_length(num_glyphs);
         let index_format = table_buf.get_u16();
+        // 0 is short offsets and 1 is long offsets; nothing else is defined
+        if index_format > 1 {
+            return Err(GlyfDecoderError::InvalidIndexFormat(index_format));
+        }
         let n_contour_stream_size = table_buf.get_u32();
         let n_points_stream_size = table_buf.get_u32();
         let flag_stream_size = table_buf.get_u32();
         let glyph_stream_size = table_buf.get_u32();
         let composite_stream_size = table_buf.get_u32();
         let bbox_bitmap_size = bitmap_stream_length;
-        let bbox_stream_size = table_buf.get_u32() - bbox_bitmap_size as u32;
+        // the declared bbox stream size includes the bitmap
+        let bbox_stream_size = (table_buf.get_u32() as usize)
+            .checked_sub(bbox_bitmap_size)
+            .ok_or(GlyfDecoderError::Truncated)?;
         let instruction_stream_size = table_buf.get_u32();
         assert_eq!(table_buf.position() as usize, GLYF_HEADER_SIZE);
         let has_overlap_bit_stream = (option_flags & 0x01) == 0x01;
@@ -98,49 +150,47 @@ impl<'a> Woff2GlyfDecoder<'a, &'a [u8]> {
             0
         };
 
-        let n_contour_stream_start: usize = table_buf.position().try_into().unwrap();
-        let n_points_stream_start = n_contour_stream_start + n_contour_stream_size as usize;
-        let flag_stream_start = n_points_stream_start + n_points_stream_size as usize;
-        let glyph_stream_start = flag_stream_start + flag_stream_size as usize;
-        let composite_stream_start = glyph_stream_start + glyph_stream_size as usize;
-        let bbox_bitmap_start = composite_stream_start + composite_stream_size as usize;
-        let bbox_stream_start = bbox_bitmap_start + bbox_bitmap_size as usize;
-        let instruction_stream_start = bbox_stream_start + bbox_stream_size as usize;
-        let overlap_bit_stream_start = instruction_stream_start + instruction_stream_size as usize;
-        let overlap_bit_stream_end =
-            overlap_bit_stream_start + overlap_simple_bit_stream_size as usize;
-        if transformed_glyf_table.len() < overlap_bit_stream_end {
-            return Err(GlyfDecoderError::Truncated);
-        }
-        let n_contour_stream =
-            Cursor::new(&transformed_glyf_table[n_contour_stream_start..n_points_stream_start]);
-        let n_points_stream =
-            Cursor::new(&transformed_glyf_table[n_points_stream_start..flag_stream_start]);
-        let flag_stream =
-            Cursor::new(&transformed_glyf_table[flag_stream_start..glyph_stream_start]);
-        let glyph_stream =
-            Cursor::new(&transformed_glyf_table[glyph_stream_start..composite_stream_start]);
-        let composite_stream =
-            Cursor::new(&transformed_glyf_table[composite_stream_start..bbox_bitmap_start]);
-        let bbox_bitmap = BitSlice::<_, Msb0>::from_slice(
-            &transformed_glyf_table[bbox_bitmap_start..bbox_stream_start],
-        );
-        let bbox_stream =
-            Cursor::new(&transformed_glyf_table[bbox_stream_start..instruction_stream_start]);
-        let instruction_stream = Cursor::new(
-            &transformed_glyf_table[instruction_stream_start..overlap_bit_stream_start],
-        );
+        // Each stream starts where the previous one ends. Checking every boundary (rather than
+        // just the last one) keeps a size that overflows from wrapping around to an earlier
+        // position, so the streams can't overlap or go past the end of the table.
+        let mut stream_end = GLYF_HEADER_SIZE;
+        let mut next_stream = |size: usize| {
+            let start = stream_end;
+            stream_end = start
+                .checked_add(size)
+                .filter(|&end| end <= transformed_glyf_table.len())
+                .ok_or(GlyfDecoderError::Truncated)?;
+            debug_assert!(stream_end >= start);
+            Ok::<_, GlyfDecoderError>(start..stream_end)
+        };
+        let n_contour_stream = next_stream(n_contour_stream_size as usize)?;
+        let n_points_stream = next_stream(n_points_stream_size as usize)?;
+        let flag_stream = next_stream(flag_stream_size as usize)?;
+        let glyph_stream = next_stream(glyph_stream_size as usize)?;
+        let composite_stream = next_stream(composite_stream_size as usize)?;
+        let bbox_bitmap = next_stream(bbox_bitmap_size)?;
+        let bbox_stream = next_stream(bbox_stream_size)?;
+        let instruction_stream = next_stream(instruction_stream_size as usize)?;
         let overlap_bitmap = if has_overlap_bit_stream {
-            Some(BitSlice::<_, Msb0>::from_slice(
-                &transformed_glyf_table[overlap_bit_stream_start
-                    ..overlap_bit_stream_start + overlap_simple_bit_stream_size as usize],
-            ))
+            Some(next_stream(overlap_simple_bit_stream_size)?)
         } else {
             None
         };
+        // the table is just the header and the streams, so there mustn't be anything after them
+        if stream_end != transformed_glyf_table.len() {
+            return Err(GlyfDecoderError::ExtraData);
+        }
+        // the bitmaps are indexed by glyph; they're sized for `num_glyphs`, and a table too short
+        // to hold them was rejected above
+        let covers_all_glyphs = |bitmap: &Range<usize>| bitmap.len() * 8 >= num_glyphs as usize;
+        debug_assert!(covers_all_glyphs(&bbox_bitmap));
+        if let Some(overlap_bitmap) = &overlap_bitmap {
+            debug_assert!(covers_all_glyphs(overlap_bitmap));
+        }
 
-        Ok(Self {
+        Ok(GlyfStreamLayout {
             num_glyphs,
+            index_format,
             n_contour_stream,
             n_points_stream,
             flag_stream,
@@ -150,7 +200,42 @@ impl<'a> Woff2GlyfDecoder<'a, &'a [u8]> {
             bbox_stream,
             instruction_stream,
             overlap_bitmap,
-            index_format,
+        })
+    }
+}
+
+impl<'a> Woff2GlyfDecoder<'a, &'a [u8]> {
+    fn has_read_all(&self) -> bool {
+        let _n_contour_stream_remaining = self.n_contour_stream.remaining();
+        let _n_points_stream_reminaing = self.n_points_stream.remaining();
+
+        self.n_contour_stream.remaining() == 0
+            && self.n_points_stream.remaining() == 0
+            && self.flag_stream.remaining() == 0
+            && self.glyph_stream.remaining() == 0
+            && self.composite_stream.remaining() == 0
+            && self.bbox_stream.remaining() == 0
+            && self.instruction_stream.remaining() == 0
+    }
+
+    fn new(transformed_glyf_table: &'a [u8]) -> Result<Self, GlyfDecoderError> {
+        let layout = GlyfStreamLayout::from_slice(transformed_glyf_table)?;
+        let stream = |range: &Range<usize>| Cursor::new(&transformed_glyf_table[range.clone()]);
+        let bitmap =
+            |range: &Range<usize>| BitSlice::from_slice(&transformed_glyf_table[range.clone()]);
+        Ok(Self {
+            num_glyphs: layout.num_glyphs,
+            n_contour_stream: stream(&layout.n_contour_stream),
+            n_points_stream: stream(&layout.n_points_stream),
+            flag_stream: stream(&layout.flag_stream),
+            glyph_stream: stream(&layout.glyph_stream),
+            composite_stream: stream(&layout.composite_stream),
+            bbox_bitmap: bitmap(&layout.bbox_bitmap),
+            bbox_stream: stream(&layout.bbox_stream),
+            instruction_stream: stream(&layout.instruction_stream),
+            overlap_bitmap: layout.overlap_bitmap.as_ref().map(bitmap),
+            index_format: layout.index_format,
+            max_points_per_glyph: DEFAULT_MAX_POINTS_PER_GLYPH,
         })
     }
 
@@ -168,25 +253,39 @@ impl<'a> Woff2GlyfDecoder<'a, &'a [u8]> {
 
         let mut running_total_points: u16 = 0;
 
-        let overlap_simple_flag = match self.overlap_bitmap {
-            Some(ob) if ob[glyph_index as usize] => 0x40,
-            _ => 0x00,
+        let overlap_simple_flag = if self.has_overlap_simple(glyph_index) {
+            0x40
+        } else {
+            0x00
         };
 
-        let mut x_min = 0i16;
-        let mut y_min = 0i16;
-        let mut x_max = 0i16;
-        let mut y_max = 0i16;
+        // the coordinates of a valid glyph fit in an `i16`, but the deltas of a crafted one can add
+        // up to more, so they're summed in an `i32` to keep the bounding box from wrapping around
+        let mut x_min = 0i32;
+        let mut y_min = 0i32;
+        let mut x_max = 0i32;
+        let mut y_max = 0i32;
         let mut extents_set: bool = false;
-        let mut x = 0i16;
-        let mut y = 0i16;
+        let mut x = 0i32;
+        let mut y = 0i32;
 
         for _contour_index in 0..number_of_contours {
             let number_of_points = self.n_points_stream.try_get_255_u16()?;
-            running_total_points += number_of_points;
+            // checked before reading any of the points, so that a crafted point count can't make
+            // the output grow any further
+            running_total_points = running_total_points
+                .checked_add(number_of_points)
+                .filter(|&total| total <= self.max_points_per_glyph)
+                .ok_or(GlyfDecoderError::TooManyPoints {
+                    glyph_index,
+                    limit: self.max_points_per_glyph,
+                })?;
             end_points_of_contours_stream.put_u16(running_total_points - 1);
             for _point_index in 0..number_of_points {
                 let flags = self.flag_stream.try_get_u8()?;
+                // the top bit is the on-curve flag and the other seven bits pick one of the 128
+                // triplet encodings, so every flag byte is valid
+                debug_assert_eq!(COORD_LUT.len(), 128);
                 let triplet = &COORD_LUT[(flags & 0x7f) as usize];
                 let data = match triplet.byte_count {
                     1 => self.glyph_stream.try_get_u8()? as u32,
@@ -200,8 +299,8 @@ impl<'a> Woff2GlyfDecoder<'a, &'a [u8]> {
                 };
                 let dx = triplet.dx(data);
                 let dy = triplet.dy(data);
-                x += dx;
-                y += dy;
+                x = x.saturating_add(dx.into());
+                y = y.saturating_add(dy.into());
                 if extents_set {
                     x_min = x_min.min(x);
                     y_min = y_min.min(y);
@@ -259,22 +358,32 @@ impl<'a> Woff2GlyfDecoder<'a, &'a [u8]> {
             }
         }
 
-        let instruction_length = self.glyph_stream.try_get_255_u16()?;
+        let instruction_length = self.read_instruction_length()?;
         self.instruction_stream
             .try_copy_to_buf(&mut instructions_stream, instruction_length as usize)?;
 
-        if self.bbox_bitmap[glyph_index as usize] {
-            x_min = self.bbox_stream.try_get_i16()?;
-            y_min = self.bbox_stream.try_get_i16()?;
-            x_max = self.bbox_stream.try_get_i16()?;
-            y_max = self.bbox_stream.try_get_i16()?;
-        }
+        let bbox = if self.bbox_bitmap[glyph_index as usize] {
+            [
+                self.bbox_stream.try_get_i16()?,
+                self.bbox_stream.try_get_i16()?,
+                self.bbox_stream.try_get_i16()?,
+                self.bbox_stream.try_get_i16()?,
+            ]
+        } else {
+            let bbox = [x_min, y_min, x_max, y_max].map(clamp_to_i16);
+            if bbox.map(i32::from) != [x_min, y_min, x_max, y_max] {
+                warn!(
+                    "Coordinates of glyph {} out of range, clamping its bounding box",
+                    glyph_index
+                );
+            }
+            bbox
+        };
 
         output_buffer.put_i16(number_of_contours);
-        output_buffer.put_i16(x_min);
-        output_buffer.put_i16(y_min);
-        output_buffer.put_i16(x_max);
-        output_buffer.put_i16(y_max);
+        for extent in bbox {
+            output_buffer.put_i16(extent);
+        }
         output_buffer.write_all(&end_points_of_contours_stream)?;
         output_buffer.put_u16(instruction_length);
         output_buffer.write_all(&instructions_stream)?;
@@ -285,6 +394,16 @@ impl<'a> Woff2GlyfDecoder<'a, &'a [u8]> {
         Ok(())
     }
 
+    /// Reads the length of a glyph's instructions, checking that the instruction stream has that
+    /// many bytes left before anything is written.
+    fn read_instruction_length(&mut self) -> Result<u16, GlyfDecoderError> {
+        let instruction_length = self.glyph_stream.try_get_255_u16()?;
+        if usize::from(instruction_length) > self.instruction_stream.remaining() {
+            return Err(GlyfDecoderError::Truncated);
+        }
+        Ok(instruction_length)
+    }
+
     fn parse_composite_glyph(
         &mut self,
         glyph_index: u16,
@@ -301,8 +420,18 @@ impl<'a> Woff2GlyfDecoder<'a, &'a [u8]> {
         }
 
         let mut have_instructions = false;
-        loop {
+        for num_components in 1.. {
+            if num_components > MAX_COMPONENTS_PER_GLYPH {
+                return Err(GlyfDecoderError::TooManyComponents(glyph_index));
+            }
             let flag_word = self.composite_stream.try_get_u16()?;
+            // reserved bits must be zero, and at most one kind of scale can be given; otherwise
+            // we can't be sure how many bytes the component takes up
+            const RESERVED_FLAGS: u16 = 0xE010;
+            const SCALE_FLAGS: u16 = 0x0008 | 0x0040 | 0x0080;
+            if flag_word & RESERVED_FLAGS != 0 || (flag_word & SCALE_FLAGS).count_ones() > 1 {
+                return Err(GlyfDecoderError::InvalidCompositeFlags(flag_word));
+            }
             let mut num_bytes = 4usize;
 
             if flag_word & 0x0001 == 0x0001 {
@@ -331,7 +460,7 @@ impl<'a> Woff2GlyfDecoder<'a, &'a [u8]> {
         }
 
         if have_instructions {
-            let instruction_length = self.glyph_stream.try_get_255_u16()?;
+            let instruction_length = self.read_instruction_length()?;
             output_buffer.put_u16(instruction_length);
             self.instruction_stream
                 .try_copy_to_buf(output_buffer, instruction_length as usize)?;
@@ -347,6 +476,12 @@ impl<'a> Woff2GlyfDecoder<'a, &'a [u8]> {
     ) -> Result<(), GlyfDecoderError> {
         let number_of_contours = self.n_contour_stream.try_get_i16()?;
         match number_of_contours {
+            // Spec: https://www.w3.org/TR/WOFF2/#glyf_table_format
+            // An empty glyph has no bounding box, so its bboxBitmap bit must not be set. Rejecting
+            // it here keeps the bbox stream from falling out of step with later glyphs.
+            0 if self.bbox_bitmap[glyph_index as usize] => {
+                Err(GlyfDecoderError::EmptyGlyphWithBbox)
+            }
             0 => Ok(()),
             num if num > 0 => {
                 self.parse_simple_glyph(number_of_contours, glyph_index, output_vector)
@@ -355,34 +490,88 @@ impl<'a> Woff2GlyfDecoder<'a, &'a [u8]> {
         }
     }
 
-    fn parse_all_glyphs(&mut self) -> Result<(Vec<u8>, Vec<u8>), GlyfDecoderError> {
-        let loca_use_u32 = self.index_format > 0;
-        let loca_capacity = (self.num_glyphs + 1) as usize * if loca_use_u32 { 4 } else { 2 };
+    /// Returns whether the overlap bitmap marks the glyph as having overlapping contours.
+    fn has_overlap_simple(&self, glyph_index: u16) -> bool {
+        self.overlap_bitmap
+            .is_some_and(|overlap_bitmap| overlap_bitmap[glyph_index as usize])
+    }
+
+    fn parse_all_glyphs(&mut self) -> Result<DecodedGlyf, GlyfDecoderError> {
         let mut output_glyf_table: Vec<u8> = Vec::new();
-        let mut output_loca_table: Vec<u8> = Vec::with_capacity(loca_capacity);
+        let mut glyph_offsets = Vec::with_capacity(self.num_glyphs as usize + 1);
+        let mut x_mins = Vec::with_capacity(self.num_glyphs as usize);
         for glyph_index in 0..self.num_glyphs {
-            if loca_use_u32 {
-                output_loca_table.put_u32(output_glyf_table.len().try_into().unwrap());
-            } else {
-                output_loca_table.put_u16((output_glyf_table.len() / 2).try_into().unwrap());
-            }
+            let glyph_start = output_glyf_table.len();
+            glyph_offsets.push(glyph_start);
             self.parse_next_glyph(glyph_index, &mut output_glyf_table)?;
+            // empty glyphs have no bounding box, so their xMin is taken to be 0
+            x_mins.push(
+                match output_glyf_table.get(glyph_start + 2..glyph_start + 4) {
+                    Some(x_min) => i16::from_be_bytes(x_min.try_into().unwrap()),
+                    None => 0,
+                },
+            );
             pad_to_multiple_of_four(&mut output_glyf_table);
         }
-        if loca_use_u32 {
-            output_loca_table.put_u32(output_glyf_table.len().try_into().unwrap());
-        } else {
-            if output_glyf_table.len() % 2 == 1 {
-                output_glyf_table.put_u8(0);
+        glyph_offsets.push(output_glyf_table.len());
+
+        // short offsets are stored divided by two, so they can't address more than 128 KiB; if the
+        // glyphs don't fit, the whole table gets long offsets instead
+        let index_format =
+            if self.index_format == 0 && output_glyf_table.len() / 2 <= u16::MAX as usize {
+                0
+            } else {
+                1
+            };
+        if index_format != self.index_format {
+            debug!(
+                "Promoting `loca` to long offsets for a {} byte `glyf` table",
+                output_glyf_table.len()
+            );
+        }
+        let entry_size = if index_format == 0 { 2 } else { 4 };
+        let mut output_loca_table = Vec::with_capacity(glyph_offsets.len() * entry_size);
+        for offset in glyph_offsets {
+            if index_format == 0 {
+                // every glyph is padded to four bytes, so the offsets are even
+                output_loca_table.put_u16((offset / 2) as u16);
+            } else {
+                output_loca_table.put_u32(offset.try_into().expect("glyf table larger than 4 GiB"));
             }
-            output_loca_table.put_u16((output_glyf_table.len() / 2).try_into().unwrap());
         }
-        Ok((output_glyf_table, output_loca_table))
+        Ok(DecodedGlyf {
+            glyf: output_glyf_table,
+            loca: output_loca_table,
+            index_format,
+            x_mins,
+        })
     }
 }
 
-pub fn decode_glyf_table<'a>(glyf_table: &'a [u8]) -> Result<(Vec<u8>, Vec<u8>), GlyfDecoderError> {
+/// The tables reconstructed from a transformed `glyf` table.
+pub struct DecodedGlyf {
+    pub glyf: Vec<u8>,
+    pub loca: Vec<u8>,
+    /// The index format of `loca`, to be used as `indexToLocFormat` in the `head` table. This is
+    /// the format declared in the transformed table, unless short offsets can't address all of
+    /// `glyf`, in which case it's 1 (long offsets).
+    pub index_format: u16,
+    /// The `xMin` of each glyph, for reconstructing a transformed `hmtx` table
+    pub x_mins: Vec<i16>,
+}
+
+pub fn decode_glyf_table<'a>(glyf_table: &'a [u8]) -> Result<DecodedGlyf, GlyfDecoderError> {
+    decode_glyf_table_with_max_points(glyf_table, DEFAULT_MAX_POINTS_PER_GLYPH)
+}
+
+/// Like [`decode_glyf_table`], but rejects simple glyphs with more than `max_points_per_glyph`
+/// points with [`GlyfDecoderError::TooManyPoints`].
+pub fn decode_glyf_table_with_max_points(
+    glyf_table: &[u8],
+    max_points_per_glyph: u16,
+) -> Result<DecodedGlyf, GlyfDecoderError> {
     let mut decoder = Woff2GlyfDecoder::new(glyf_table)?;
+    decoder.max_points_per_glyph = max_points_per_glyph;
     let res = decoder.parse_all_glyphs()?;
     if decoder.has_read_all() {
         Ok(res)
@@ -390,3 +579,1199 @@ pub fn decode_glyf_table<'a>(glyf_table: &'a [u8]) -> Result<(Vec<u8>, Vec<u8>),
         Err(GlyfDecoderError::ExtraData)
     }
 }
+
+/// Like [`decode_glyf_table`], but reads the transformed table from `reader` into a buffer first,
+/// since the decoder needs all of it at once.
+pub fn decode_glyf_table_from_reader(
+    mut reader: impl Read,
+) -> Result<DecodedGlyf, GlyfDecoderError> {
+    let mut glyf_table = Vec::new();
+    reader
+        .read_to_end(&mut glyf_table)
+        .map_err(GlyfDecoderError::Read)?;
+    decode_glyf_table(&glyf_table)
+}
+
+/// Decodes the glyphs of a transformed `glyf` table one at a time, yielding each [`Glyph`].
+///
+/// Unlike [`decode_glyf_table`], no `glyf` or `loca` table is built. Decoding stops after the
+/// first error. Leftover data after the last glyph is reported as [`GlyfDecoderError::ExtraData`].
+pub fn glyphs(transformed_glyf_table: &[u8]) -> Result<Glyphs<'_>, GlyfDecoderError> {
+    let decoder = Woff2GlyfDecoder::new(transformed_glyf_table)?;
+    Ok(Glyphs {
+        glyph_indices: 0..decoder.num_glyphs,
+        decoder,
+        finished: false,
+    })
+}
+
+/// A glyph decoded from a transformed `glyf` table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Glyph {
+    pub index: u16,
+    /// The `glyf` data of the glyph, empty for empty glyphs and without padding
+    pub data: Vec<u8>,
+    /// Whether the glyph is marked in the overlap bitmap. The decoder sets the OVERLAP_SIMPLE flag
+    /// on every point of such glyphs if they're simple glyphs.
+    pub overlap_simple: bool,
+}
+
+impl Glyph {
+    /// Returns whether the glyph has no outline.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+/// Iterator over the glyphs of a transformed `glyf` table, created by [`glyphs`].
+pub struct Glyphs<'a> {
+    decoder: Woff2GlyfDecoder<'a, &'a [u8]>,
+    glyph_indices: Range<u16>,
+    finished: bool,
+}
+
+impl Iterator for Glyphs<'_> {
+    type Item = Result<Glyph, GlyfDecoderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let Some(glyph_index) = self.glyph_indices.next() else {
+            self.finished = true;
+            return (!self.decoder.has_read_all()).then_some(Err(GlyfDecoderError::ExtraData));
+        };
+        let mut data = Vec::new();
+        match self.decoder.parse_next_glyph(glyph_index, &mut data) {
+            Ok(()) => Some(Ok(Glyph {
+                index: glyph_index,
+                data,
+                overlap_simple: self.decoder.has_overlap_simple(glyph_index),
+            })),
+            Err(error) => {
+                // the streams are out of step after an error, so later glyphs would be garbage
+                self.finished = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+/// Looks up where glyph `glyph_index` is stored in the `glyf` table, using a `loca` table with the
+/// given index format.
+///
+/// Returns `None` if `loca` is too short or its offsets go backwards.
+pub fn glyph_range(
+    loca: &[u8],
+    index_format: u16,
+    glyph_index: usize,
+) -> Option<std::ops::Range<usize>> {
+    let offset = |index: usize| -> Option<usize> {
+        if index_format == 0 {
+            let bytes = loca.get(index * 2..index * 2 + 2)?;
+            // short offsets are stored divided by two
+            Some(u16::from_be_bytes(bytes.try_into().unwrap()) as usize * 2)
+        } else {
+            let bytes = loca.get(index * 4..index * 4 + 4)?;
+            Some(u32::from_be_bytes(bytes.try_into().unwrap()) as usize)
+        }
+    };
+    let start = offset(glyph_index)?;
+    let end = offset(glyph_index + 1)?;
+    (start <= end).then_some(start..end)
+}
+
+/// Rebuilds the `loca` table of an untransformed `glyf` table by walking its glyphs, for fonts
+/// whose encoder transformed `loca` on its own. `loca_length` is the original length of `loca`,
+/// which gives the number of glyphs.
+///
+/// The glyphs are assumed to be stored in order, each padded to two or four bytes. Empty glyphs
+/// take up no space, so there's no telling where they'd go; returns `None` unless `glyf` holds
+/// exactly one outline per glyph.
+pub(crate) fn rebuild_loca(glyf: &[u8], index_format: u16, loca_length: usize) -> Option<Vec<u8>> {
+    let entry_size = if index_format == 0 { 2 } else { 4 };
+    if loca_length % entry_size != 0 {
+        return None;
+    }
+    let num_glyphs = (loca_length / entry_size).checked_sub(1)?;
+    let mut loca = Vec::with_capacity(loca_length);
+    let mut put_offset = |offset: usize| -> Option<()> {
+        if index_format == 0 {
+            // short offsets are stored divided by two
+            loca.put_u16(u16::try_from(offset / 2).ok()?);
+        } else {
+            loca.put_u32(u32::try_from(offset).ok()?);
+        }
+        Some(())
+    };
+    let mut offset = 0;
+    for _ in 0..num_glyphs {
+        if offset >= glyf.len() {
+            return None;
+        }
+        put_offset(offset)?;
+        offset += glyph_length(&glyf[offset..])?;
+        offset += offset & 1;
+        if offset % 4 == 2 && glyf.get(offset..offset + 2) == Some(&[0, 0]) {
+            offset += 2;
+        }
+    }
+    // only padding may be left over
+    if glyf
+        .get(offset..)
+        .is_some_and(|rest| rest.iter().any(|&byte| byte != 0))
+    {
+        return None;
+    }
+    put_offset(glyf.len())?;
+    Some(loca)
+}
+
+/// Returns the length of the untransformed glyph at the start of `glyph`, without padding.
+fn glyph_length(glyph: &[u8]) -> Option<usize> {
+    let mut data = glyph;
+    let number_of_contours = data.try_get_i16().ok()?;
+    // bounding box
+    data = data.get(8..)?;
+    if number_of_contours >= 0 {
+        let end_points_length = 2 * number_of_contours as usize;
+        let num_points = match data.get(..end_points_length)? {
+            [.., high, low] => u16::from_be_bytes([*high, *low]) as usize + 1,
+            _ => 0,
+        };
+        data = &data[end_points_length..];
+        let instruction_length = data.try_get_u16().ok()?;
+        data = data.get(instruction_length as usize..)?;
+        let mut points = 0;
+        let mut coordinates_length = 0;
+        while points < num_points {
+            let flag = data.try_get_u8().ok()?;
+            let count = if flag & 0x08 != 0 {
+                data.try_get_u8().ok()? as usize + 1
+            } else {
+                1
+            };
+            let coordinate_size = |short_flag: u8, same_flag: u8| match flag {
+                _ if flag & short_flag != 0 => 1,
+                _ if flag & same_flag != 0 => 0,
+                _ => 2,
+            };
+            coordinates_length +=
+                count * (coordinate_size(0x02, 0x10) + coordinate_size(0x04, 0x20));
+            points += count;
+        }
+        data = data.get(coordinates_length..)?;
+    } else {
+        let mut have_instructions = false;
+        loop {
+            let flags = data.try_get_u16().ok()?;
+            let argument_size = if flags & 0x0001 != 0 { 4 } else { 2 };
+            let scale_size = match flags {
+                _ if flags & 0x0008 != 0 => 2,
+                _ if flags & 0x0040 != 0 => 4,
+                _ if flags & 0x0080 != 0 => 8,
+                _ => 0,
+            };
+            // glyph index, arguments and scale
+            data = data.get(2 + argument_size + scale_size..)?;
+            have_instructions |= flags & 0x0100 != 0;
+            if flags & 0x0020 == 0 {
+                break;
+            }
+        }
+        if have_instructions {
+            let instruction_length = data.try_get_u16().ok()?;
+            data = data.get(instruction_length as usize..)?;
+        }
+    }
+    Some(glyph.len() - data.len())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use bytes::{Buf, BufMut};
+
+    use super::{
+        bit_stream_byte_length, decode_glyf_table, decode_glyf_table_from_reader,
+        decode_glyf_table_with_max_points, glyph_range, glyphs, rebuild_loca, DecodedGlyf,
+        GlyfDecoderError, GlyfStreamLayout, Woff2GlyfDecoder, COORD_LUT, MAX_COMPONENTS_PER_GLYPH,
+    };
+    use crate::buffer_util::BufMutExt;
+
+    /// The streams making up a transformed `glyf` table, for building test tables.
+    #[derive(Clone, Default)]
+    pub(crate) struct TransformedGlyf {
+        pub num_glyphs: u16,
+        pub index_format: u16,
+        pub n_contour_stream: Vec<u8>,
+        pub n_points_stream: Vec<u8>,
+        pub flag_stream: Vec<u8>,
+        pub glyph_stream: Vec<u8>,
+        pub composite_stream: Vec<u8>,
+        /// Glyph indices with an explicit bounding box
+        pub bbox_glyphs: Vec<u16>,
+        pub bbox_stream: Vec<u8>,
+        pub instruction_stream: Vec<u8>,
+        /// Glyph indices with the OVERLAP_SIMPLE flag, if the overlap bitmap is present
+        pub overlap_glyphs: Option<Vec<u16>>,
+    }
+
+    fn bitmap(num_glyphs: u16, set: &[u16]) -> Vec<u8> {
+        let mut bitmap = vec![0u8; bit_stream_byte_length(num_glyphs)];
+        for &glyph_index in set {
+            bitmap[glyph_index as usize / 8] |= 0x80 >> (glyph_index % 8);
+        }
+        bitmap
+    }
+
+    impl TransformedGlyf {
+        pub fn to_bytes(&self) -> Vec<u8> {
+            let bbox_bitmap = bitmap(self.num_glyphs, &self.bbox_glyphs);
+            let mut table = Vec::new();
+            table.put_u16(0);
+            table.put_u16(self.overlap_glyphs.is_some() as u16);
+            table.put_u16(self.num_glyphs);
+            table.put_u16(self.index_format);
+            table.put_u32(self.n_contour_stream.len() as u32);
+            table.put_u32(self.n_points_stream.len() as u32);
+            table.put_u32(self.flag_stream.len() as u32);
+            table.put_u32(self.glyph_stream.len() as u32);
+            table.put_u32(self.composite_stream.len() as u32);
+            table.put_u32((bbox_bitmap.len() + self.bbox_stream.len()) as u32);
+            table.put_u32(self.instruction_stream.len() as u32);
+            table.put_slice(&self.n_contour_stream);
+            table.put_slice(&self.n_points_stream);
+            table.put_slice(&self.flag_stream);
+            table.put_slice(&self.glyph_stream);
+            table.put_slice(&self.composite_stream);
+            table.put_slice(&bbox_bitmap);
+            table.put_slice(&self.bbox_stream);
+            table.put_slice(&self.instruction_stream);
+            if let Some(overlap_glyphs) = &self.overlap_glyphs {
+                table.put_slice(&bitmap(self.num_glyphs, overlap_glyphs));
+            }
+            table
+        }
+
+        /// Applies the glyf transform to `glyf` and `loca`, always picking the first triplet
+        /// encoding in `COORD_LUT` that fits a point, and only storing a bounding box when it
+        /// differs from the one calculated from the points.
+        pub fn from_glyf(glyf: &[u8], loca: &[u8], index_format: u16) -> TransformedGlyf {
+            let entry_size = if index_format == 0 { 2 } else { 4 };
+            let num_glyphs = (loca.len() / entry_size - 1) as u16;
+            let mut transformed = TransformedGlyf {
+                num_glyphs,
+                index_format,
+                ..Default::default()
+            };
+            let mut overlap_glyphs = Vec::new();
+            for glyph_index in 0..num_glyphs {
+                let range = glyph_range(loca, index_format, glyph_index as usize).unwrap();
+                let mut glyph = &glyf[range];
+                if glyph.is_empty() {
+                    transformed.n_contour_stream.put_i16(0);
+                    continue;
+                }
+                let number_of_contours = glyph.get_i16();
+                transformed.n_contour_stream.put_i16(number_of_contours);
+                let bbox = &glyph[..8];
+                glyph.advance(8);
+                if number_of_contours < 0 {
+                    transformed.bbox_glyphs.push(glyph_index);
+                    transformed.bbox_stream.put_slice(bbox);
+                    transformed.add_composite_glyph(glyph);
+                } else {
+                    let (calculated_bbox, overlap) =
+                        transformed.add_simple_glyph(number_of_contours as u16, glyph);
+                    if bbox != calculated_bbox {
+                        transformed.bbox_glyphs.push(glyph_index);
+                        transformed.bbox_stream.put_slice(bbox);
+                    }
+                    if overlap {
+                        overlap_glyphs.push(glyph_index);
+                    }
+                }
+            }
+            if !overlap_glyphs.is_empty() {
+                transformed.overlap_glyphs = Some(overlap_glyphs);
+            }
+            transformed
+        }
+
+        /// Adds a simple glyph after its bounding box, returning the bounding box of its points
+        /// and whether it has the OVERLAP_SIMPLE flag.
+        fn add_simple_glyph(
+            &mut self,
+            number_of_contours: u16,
+            mut glyph: &[u8],
+        ) -> ([u8; 8], bool) {
+            let mut previous_end_point = -1i32;
+            for _ in 0..number_of_contours {
+                let end_point = glyph.get_u16() as i32;
+                self.n_points_stream
+                    .put_255_u16((end_point - previous_end_point) as u16);
+                previous_end_point = end_point;
+            }
+            let num_points = (previous_end_point + 1) as usize;
+            let instruction_length = glyph.get_u16();
+            let (instructions, mut glyph) = glyph.split_at(instruction_length as usize);
+
+            let mut flags = Vec::with_capacity(num_points);
+            while flags.len() < num_points {
+                let flag = glyph.get_u8();
+                let repeat = if flag & 0x08 != 0 { glyph.get_u8() } else { 0 };
+                flags.extend(std::iter::repeat_n(flag, repeat as usize + 1));
+            }
+            let read_coordinates = |glyph: &mut &[u8], short_flag: u8, same_flag: u8| {
+                flags
+                    .iter()
+                    .map(
+                        |&flag| match (flag & short_flag != 0, flag & same_flag != 0) {
+                            (true, true) => glyph.get_u8() as i16,
+                            (true, false) => -(glyph.get_u8() as i16),
+                            (false, true) => 0,
+                            (false, false) => glyph.get_i16(),
+                        },
+                    )
+                    .collect::<Vec<_>>()
+            };
+            let dxs = read_coordinates(&mut glyph, 0x02, 0x10);
+            let dys = read_coordinates(&mut glyph, 0x04, 0x20);
+
+            let (mut x, mut y) = (0i16, 0i16);
+            let mut bbox = None;
+            for ((&flag, &dx), &dy) in flags.iter().zip(&dxs).zip(&dys) {
+                let (index, data) = encode_triplet(dx, dy);
+                let off_curve = if flag & 0x01 == 0 { 0x80 } else { 0 };
+                self.flag_stream.put_u8(index | off_curve);
+                let byte_count = COORD_LUT[index as usize].byte_count as usize;
+                self.glyph_stream
+                    .put_slice(&data.to_be_bytes()[4 - byte_count..]);
+                x += dx;
+                y += dy;
+                let (x_min, y_min, x_max, y_max) = bbox.unwrap_or((x, y, x, y));
+                bbox = Some((x_min.min(x), y_min.min(y), x_max.max(x), y_max.max(y)));
+            }
+            self.glyph_stream.put_255_u16(instruction_length);
+            self.instruction_stream.put_slice(instructions);
+
+            let (x_min, y_min, x_max, y_max) = bbox.unwrap_or_default();
+            let mut calculated_bbox = [0; 8];
+            let mut buffer = &mut calculated_bbox[..];
+            for value in [x_min, y_min, x_max, y_max] {
+                buffer.put_i16(value);
+            }
+            let overlap = flags.first().is_some_and(|&flag| flag & 0x40 != 0);
+            (calculated_bbox, overlap)
+        }
+
+        /// Adds the components and instructions of a composite glyph after its bounding box.
+        fn add_composite_glyph(&mut self, mut glyph: &[u8]) {
+            let mut have_instructions = false;
+            loop {
+                let flags = glyph.get_u16();
+                let argument_size = if flags & 0x0001 != 0 { 4 } else { 2 };
+                let scale_size = match flags {
+                    _ if flags & 0x0008 != 0 => 2,
+                    _ if flags & 0x0040 != 0 => 4,
+                    _ if flags & 0x0080 != 0 => 8,
+                    _ => 0,
+                };
+                self.composite_stream.put_u16(flags);
+                // glyph index, arguments and scale
+                let component_size = 2 + argument_size + scale_size;
+                self.composite_stream.put_slice(&glyph[..component_size]);
+                glyph.advance(component_size);
+                have_instructions |= flags & 0x0100 != 0;
+                if flags & 0x0020 == 0 {
+                    break;
+                }
+            }
+            if have_instructions {
+                let instruction_length = glyph.get_u16();
+                self.glyph_stream.put_255_u16(instruction_length);
+                self.instruction_stream
+                    .put_slice(&glyph[..instruction_length as usize]);
+            }
+        }
+    }
+
+    /// Picks the first triplet encoding that can hold `(dx, dy)`, returning its index and data.
+    fn encode_triplet(dx: i16, dy: i16) -> (u8, u32) {
+        let fits = |value: i16, bits: u8, delta: u16, negative: bool| {
+            let sign_matches = value == 0 || (value < 0) == negative;
+            let offset = (value.unsigned_abs() as u32).checked_sub(delta.into());
+            sign_matches && offset.is_some_and(|offset| offset < 1 << bits)
+        };
+        let (index, triplet) = COORD_LUT
+            .iter()
+            .enumerate()
+            .find(|(_, triplet)| {
+                fits(dx, triplet.x_bits, triplet.delta_x, triplet.x_is_negative)
+                    && fits(dy, triplet.y_bits, triplet.delta_y, triplet.y_is_negative)
+            })
+            .expect("every delta fits the last triplets");
+        let bits = triplet.byte_count as u32 * 8;
+        let x_data = dx.unsigned_abs() as u32 - triplet.delta_x as u32;
+        let y_data = dy.unsigned_abs() as u32 - triplet.delta_y as u32;
+        let data = (x_data << (bits - triplet.x_bits as u32))
+            | (y_data << (bits - triplet.x_bits as u32 - triplet.y_bits as u32));
+        (index as u8, data)
+    }
+
+    /// Checks that transforming `glyf` and `loca` and decoding the result gives them back.
+    fn assert_transform_round_trip(glyf: &[u8], loca: &[u8], index_format: u16) {
+        let transformed = TransformedGlyf::from_glyf(glyf, loca, index_format).to_bytes();
+        let decoded = decode_glyf_table(&transformed).unwrap();
+        assert_eq!(decoded.glyf, glyf);
+        assert_eq!(decoded.loca, loca);
+    }
+
+    #[test]
+    fn transform_round_trip() {
+        use std::io::Cursor;
+
+        use crate::{
+            decode::raw_transformed_table,
+            test_resources::{FONTAWESOME_REGULAR_400, LATO_V22_LATIN_REGULAR},
+            woff2::table_directory::GLYF_TAG,
+        };
+
+        for font in [LATO_V22_LATIN_REGULAR, FONTAWESOME_REGULAR_400] {
+            let table = raw_transformed_table(&mut Cursor::new(font), GLYF_TAG)
+                .unwrap()
+                .unwrap();
+            let index_format = u16::from_be_bytes([table[6], table[7]]);
+            let DecodedGlyf { glyf, loca, .. } = decode_glyf_table(&table).unwrap();
+            assert_transform_round_trip(&glyf, &loca, index_format);
+        }
+
+        // a composite glyph with instructions, and a simple glyph with OVERLAP_SIMPLE and a
+        // bounding box that doesn't fit its points
+        let mut composite_stream = Vec::new();
+        composite_stream.put_u16(0x0100 | 0x0001 | 0x0008);
+        composite_stream.put_slice(&[0, 1, 0x01, 0x00, 0xFF, 0x00, 0x40, 0x00]);
+        let table = TransformedGlyf {
+            num_glyphs: 2,
+            index_format: 1,
+            n_contour_stream: vec![0, 1, 0xFF, 0xFF],
+            n_points_stream: vec![3],
+            flag_stream: vec![0, 0x80 | 10, 127],
+            glyph_stream: vec![5, 200, 0x12, 0x34, 0x56, 0x78, 0, 2],
+            composite_stream,
+            bbox_glyphs: vec![0, 1],
+            bbox_stream: vec![0xFF, 0x00, 0, 0, 0, 100, 0, 100, 0, 1, 0, 2, 0, 3, 0, 4],
+            instruction_stream: vec![0xB0, 0x01],
+            overlap_glyphs: Some(vec![0]),
+        };
+        let DecodedGlyf { glyf, loca, .. } = decode_glyf_table(&table.to_bytes()).unwrap();
+        assert_transform_round_trip(&glyf, &loca, 1);
+    }
+
+    #[test]
+    fn rebuild_loca_from_glyf() {
+        // a simple glyph with OVERLAP_SIMPLE and a composite glyph with instructions
+        let mut composite_stream = Vec::new();
+        composite_stream.put_u16(0x0100 | 0x0001 | 0x0008);
+        composite_stream.put_slice(&[0, 1, 0x01, 0x00, 0xFF, 0x00, 0x40, 0x00]);
+        let table = TransformedGlyf {
+            num_glyphs: 2,
+            n_contour_stream: vec![0, 1, 0xFF, 0xFF],
+            n_points_stream: vec![3],
+            flag_stream: vec![0, 0x80 | 10, 127],
+            glyph_stream: vec![5, 200, 0x12, 0x34, 0x56, 0x78, 0, 2],
+            composite_stream,
+            bbox_glyphs: vec![1],
+            bbox_stream: vec![0, 1, 0, 2, 0, 3, 0, 4],
+            instruction_stream: vec![0xB0, 0x01],
+            overlap_glyphs: Some(vec![0]),
+            ..Default::default()
+        };
+        for index_format in [0, 1] {
+            let table = TransformedGlyf {
+                index_format,
+                ..table.clone()
+            };
+            let DecodedGlyf { glyf, loca, .. } = decode_glyf_table(&table.to_bytes()).unwrap();
+            assert_eq!(
+                Some(loca.clone()),
+                rebuild_loca(&glyf, index_format, loca.len())
+            );
+            // one glyph too many
+            assert_eq!(None, rebuild_loca(&glyf, index_format, loca.len() * 4 / 3));
+        }
+
+        // with an empty glyph, the outlines can't be matched up with glyph indices
+        let table = TransformedGlyf {
+            num_glyphs: 3,
+            n_contour_stream: [table.n_contour_stream.clone(), vec![0, 0]].concat(),
+            bbox_glyphs: vec![1],
+            ..table
+        };
+        let DecodedGlyf { glyf, loca, .. } = decode_glyf_table(&table.to_bytes()).unwrap();
+        assert_eq!(None, rebuild_loca(&glyf, 0, loca.len()));
+    }
+
+    /// Builds a transformed `glyf` table of one-point glyphs, each with `instruction_length` bytes
+    /// of instructions.
+    pub(crate) fn glyphs_with_instructions(
+        num_glyphs: u16,
+        instruction_length: u16,
+        index_format: u16,
+    ) -> Vec<u8> {
+        let mut glyph_stream = Vec::new();
+        for _ in 0..num_glyphs {
+            glyph_stream.put_u8(5);
+            glyph_stream.put_255_u16(instruction_length);
+        }
+        TransformedGlyf {
+            num_glyphs,
+            index_format,
+            n_contour_stream: [0, 1].repeat(num_glyphs as usize),
+            n_points_stream: vec![1; num_glyphs as usize],
+            flag_stream: vec![0; num_glyphs as usize],
+            glyph_stream,
+            instruction_stream: vec![0x2C; num_glyphs as usize * instruction_length as usize],
+            ..Default::default()
+        }
+        .to_bytes()
+    }
+
+    #[test]
+    fn promote_loca_to_long_offsets() {
+        // 60016 bytes per glyph, so short offsets run out in the third glyph
+        let table = glyphs_with_instructions(3, 60000, 0);
+        let DecodedGlyf {
+            glyf,
+            loca,
+            index_format,
+            ..
+        } = decode_glyf_table(&table).unwrap();
+        assert_eq!(index_format, 1);
+        assert_eq!(glyf.len(), 3 * 60016);
+        let mut expected_loca = Vec::new();
+        for offset in [0, 60016, 120032, 180048] {
+            expected_loca.put_u32(offset);
+        }
+        assert_eq!(loca, expected_loca);
+
+        // two glyphs still fit
+        let DecodedGlyf {
+            loca, index_format, ..
+        } = decode_glyf_table(&glyphs_with_instructions(2, 60000, 0)).unwrap();
+        assert_eq!(index_format, 0);
+        assert_eq!(loca, [0, 0, 0x75, 0x38, 0xEA, 0x70]);
+
+        // the end of `glyf` is the largest offset; 0x20000 bytes is just too much
+        for (instruction_length, index_format) in [(65516, 0), (65520, 1)] {
+            let table = glyphs_with_instructions(2, instruction_length, 0);
+            let decoded = decode_glyf_table(&table).unwrap();
+            assert_eq!(decoded.index_format, index_format);
+            assert_eq!(
+                glyph_range(&decoded.loca, index_format, 1),
+                Some(decoded.glyf.len() / 2..decoded.glyf.len())
+            );
+        }
+    }
+
+    #[test]
+    fn zero_glyphs_short_loca() {
+        let table = TransformedGlyf::default().to_bytes();
+        let DecodedGlyf { glyf, loca, .. } = decode_glyf_table(&table).unwrap();
+        assert!(glyf.is_empty());
+        assert_eq!(loca, [0, 0]);
+    }
+
+    #[test]
+    fn zero_glyphs_long_loca() {
+        let table = TransformedGlyf {
+            index_format: 1,
+            ..Default::default()
+        }
+        .to_bytes();
+        let DecodedGlyf { glyf, loca, .. } = decode_glyf_table(&table).unwrap();
+        assert!(glyf.is_empty());
+        assert_eq!(loca, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn zero_glyphs_with_overlap_bitmap() {
+        let table = TransformedGlyf {
+            overlap_glyphs: Some(Vec::new()),
+            ..Default::default()
+        }
+        .to_bytes();
+        let DecodedGlyf { glyf, loca, .. } = decode_glyf_table(&table).unwrap();
+        assert!(glyf.is_empty());
+        assert_eq!(loca, [0, 0]);
+    }
+
+    #[test]
+    fn empty_glyph_with_bbox_is_rejected() {
+        let table = TransformedGlyf {
+            num_glyphs: 2,
+            n_contour_stream: vec![0, 0, 0, 0],
+            bbox_glyphs: vec![0],
+            bbox_stream: vec![0; 8],
+            ..Default::default()
+        }
+        .to_bytes();
+        assert!(matches!(
+            decode_glyf_table(&table),
+            Err(GlyfDecoderError::EmptyGlyphWithBbox)
+        ));
+    }
+
+    #[test]
+    fn empty_glyphs_without_bbox() {
+        let table = TransformedGlyf {
+            num_glyphs: 2,
+            n_contour_stream: vec![0, 0, 0, 0],
+            ..Default::default()
+        }
+        .to_bytes();
+        let DecodedGlyf { glyf, loca, .. } = decode_glyf_table(&table).unwrap();
+        assert!(glyf.is_empty());
+        assert_eq!(loca, [0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn mostly_empty_glyphs() {
+        // glyph 1 is a single point with one byte of instructions (17 bytes, padded to 20), and the
+        // rest are empty
+        let table = TransformedGlyf {
+            num_glyphs: 5,
+            n_contour_stream: vec![0, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+            n_points_stream: vec![1],
+            flag_stream: vec![0],
+            glyph_stream: vec![5, 1],
+            instruction_stream: vec![0x2C],
+            ..Default::default()
+        };
+        let DecodedGlyf { glyf, loca, .. } = decode_glyf_table(&table.to_bytes()).unwrap();
+        assert_eq!(glyf.len(), 20);
+        assert_eq!(loca, [0, 0, 0, 0, 0, 10, 0, 10, 0, 10, 0, 10]);
+
+        let DecodedGlyf { glyf, loca, .. } = decode_glyf_table(
+            &TransformedGlyf {
+                index_format: 1,
+                ..table
+            }
+            .to_bytes(),
+        )
+        .unwrap();
+        assert_eq!(glyf.len(), 20);
+        assert_eq!(
+            loca,
+            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 20, 0, 0, 0, 20, 0, 0, 0, 20, 0, 0, 0, 20]
+        );
+    }
+
+    #[test]
+    fn iterate_glyphs() {
+        let table = TransformedGlyf {
+            num_glyphs: 3,
+            n_contour_stream: vec![0, 0, 0, 1, 0, 0],
+            n_points_stream: vec![1],
+            flag_stream: vec![0],
+            glyph_stream: vec![5, 1],
+            instruction_stream: vec![0x2C],
+            overlap_glyphs: Some(vec![1]),
+            ..Default::default()
+        };
+        let DecodedGlyf { glyf, loca, .. } = decode_glyf_table(&table.to_bytes()).unwrap();
+        let decoded: Vec<_> = glyphs(&table.to_bytes())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(decoded.len(), 3);
+        for glyph in &decoded {
+            let range = glyph_range(&loca, 0, glyph.index as usize).unwrap();
+            // the glyphs in `glyf` are padded
+            assert_eq!(glyf[range][..glyph.data.len()], glyph.data);
+        }
+        let overlap: Vec<_> = decoded.iter().map(|glyph| glyph.overlap_simple).collect();
+        assert_eq!(overlap, [false, true, false]);
+        assert_eq!(glyph_range(&loca, 0, 1).unwrap().len(), 20);
+
+        // errors end the iteration
+        let truncated = TransformedGlyf {
+            glyph_stream: vec![5],
+            ..table.clone()
+        }
+        .to_bytes();
+        let mut iter = glyphs(&truncated).unwrap();
+        assert!(
+            matches!(iter.next(), Some(Ok(glyph)) if glyph.index == 0 && glyph.data.is_empty())
+        );
+        assert!(matches!(
+            iter.next(),
+            Some(Err(GlyfDecoderError::Truncated))
+        ));
+        assert!(iter.next().is_none());
+
+        let extra_data = TransformedGlyf {
+            instruction_stream: vec![0x2C, 0],
+            ..table
+        };
+        let results: Vec<_> = glyphs(&extra_data.to_bytes()).unwrap().collect();
+        assert_eq!(results.len(), 4);
+        assert!(matches!(results[3], Err(GlyfDecoderError::ExtraData)));
+    }
+
+    #[test]
+    fn max_glyphs_all_empty() {
+        let table = TransformedGlyf {
+            num_glyphs: u16::MAX,
+            n_contour_stream: vec![0; 2 * u16::MAX as usize],
+            ..Default::default()
+        }
+        .to_bytes();
+        let DecodedGlyf { glyf, loca, .. } = decode_glyf_table(&table).unwrap();
+        assert!(glyf.is_empty());
+        assert_eq!(loca, vec![0; 2 * (u16::MAX as usize + 1)]);
+    }
+
+    #[test]
+    fn bitmap_lengths() {
+        assert_eq!(bit_stream_byte_length(0), 0);
+        assert_eq!(bit_stream_byte_length(1), 4);
+        assert_eq!(bit_stream_byte_length(32), 4);
+        assert_eq!(bit_stream_byte_length(33), 8);
+        assert_eq!(bit_stream_byte_length(u16::MAX), 8192);
+    }
+
+    #[test]
+    fn bitmaps_cover_all_glyphs() {
+        for num_glyphs in 0..=u16::MAX {
+            assert!(bit_stream_byte_length(num_glyphs) * 8 >= num_glyphs as usize);
+        }
+    }
+
+    #[test]
+    fn short_overlap_bitmap() {
+        let mut table = TransformedGlyf {
+            num_glyphs: 40,
+            n_contour_stream: vec![0; 2 * 40],
+            overlap_glyphs: Some(vec![39]),
+            ..Default::default()
+        }
+        .to_bytes();
+        // 40 glyphs need 8 bytes of bitmap; leave only the first 4
+        table.truncate(table.len() - 4);
+        assert!(matches!(
+            GlyfStreamLayout::from_slice(&table),
+            Err(GlyfDecoderError::Truncated)
+        ));
+        assert!(matches!(
+            decode_glyf_table(&table),
+            Err(GlyfDecoderError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn max_glyphs_with_bitmaps() {
+        let last_glyph = u16::MAX - 1;
+        let table = TransformedGlyf {
+            num_glyphs: u16::MAX,
+            n_contour_stream: vec![0; 2 * u16::MAX as usize],
+            overlap_glyphs: Some(vec![last_glyph]),
+            ..Default::default()
+        }
+        .to_bytes();
+        let DecodedGlyf { glyf, loca, .. } = decode_glyf_table(&table).unwrap();
+        assert!(glyf.is_empty());
+        assert_eq!(loca.len(), 2 * (u16::MAX as usize + 1));
+
+        // the last bit of the bbox bitmap is still read
+        let table = TransformedGlyf {
+            num_glyphs: u16::MAX,
+            n_contour_stream: vec![0; 2 * u16::MAX as usize],
+            bbox_glyphs: vec![last_glyph],
+            bbox_stream: vec![0; 8],
+            ..Default::default()
+        }
+        .to_bytes();
+        assert!(matches!(
+            decode_glyf_table(&table),
+            Err(GlyfDecoderError::EmptyGlyphWithBbox)
+        ));
+    }
+
+    #[test]
+    fn largest_triplet_index() {
+        // the last triplet encoding, with 16 bits each for x and y, both on and off the curve
+        for (flags, on_curve) in [(0x7F, 0x01), (0xFF, 0x00)] {
+            let table = TransformedGlyf {
+                num_glyphs: 1,
+                n_contour_stream: vec![0, 1],
+                n_points_stream: vec![1],
+                flag_stream: vec![flags],
+                // dx = 300, dy = 400, then no instructions
+                glyph_stream: vec![0x01, 0x2C, 0x01, 0x90, 0],
+                ..Default::default()
+            }
+            .to_bytes();
+            let DecodedGlyf { glyf, .. } = decode_glyf_table(&table).unwrap();
+            assert_eq!(
+                glyf[..19],
+                [0, 1, 1, 44, 1, 144, 1, 44, 1, 144, 0, 0, 0, 0, on_curve, 1, 44, 1, 144]
+            );
+        }
+    }
+
+    #[test]
+    fn clamp_out_of_range_bbox() {
+        // three points, each 20000 units to the right of and 30000 units above the last one, so
+        // the coordinates don't fit in an `i16`
+        let table = TransformedGlyf {
+            num_glyphs: 1,
+            n_contour_stream: vec![0, 1],
+            n_points_stream: vec![3],
+            flag_stream: vec![0x7F; 3],
+            glyph_stream: [&[0x4E, 0x20, 0x75, 0x30][..]; 3]
+                .concat()
+                .into_iter()
+                .chain([0])
+                .collect(),
+            ..Default::default()
+        }
+        .to_bytes();
+        let DecodedGlyf { glyf, .. } = decode_glyf_table(&table).unwrap();
+        let bbox: Vec<_> = glyf[2..10]
+            .chunks_exact(2)
+            .map(|extent| i16::from_be_bytes([extent[0], extent[1]]))
+            .collect();
+        assert_eq!(bbox, [20000, 30000, i16::MAX, i16::MAX]);
+        // the points themselves are stored as deltas, which are fine
+        assert_eq!(glyf[10..12], [0, 2]);
+    }
+
+    /// A glyph with no outline, followed by a composite glyph with one component using `flags`.
+    fn composite_glyph(flags: u16, arguments: &[u8]) -> Vec<u8> {
+        let mut composite_stream = Vec::new();
+        composite_stream.put_u16(flags);
+        // glyph index
+        composite_stream.put_u16(0);
+        composite_stream.put_slice(arguments);
+        TransformedGlyf {
+            num_glyphs: 2,
+            n_contour_stream: vec![0, 0, 0xFF, 0xFF],
+            composite_stream,
+            bbox_glyphs: vec![1],
+            bbox_stream: vec![0, 1, 0, 2, 0, 3, 0, 4],
+            ..Default::default()
+        }
+        .to_bytes()
+    }
+
+    #[test]
+    fn composite_glyph_arguments() {
+        // byte offsets, then word offsets with a scale
+        for (flags, arguments) in [(0x0002, &[1, 2][..]), (0x000B, &[0, 1, 0, 2, 0x40, 0])] {
+            let table = composite_glyph(flags, arguments);
+            let DecodedGlyf { glyf, .. } = decode_glyf_table(&table).unwrap();
+            let mut expected = vec![0xFF, 0xFF, 0, 1, 0, 2, 0, 3, 0, 4];
+            expected.put_u16(flags);
+            expected.put_slice(&[0, 0]);
+            expected.put_slice(arguments);
+            assert_eq!(glyf[..expected.len()], expected);
+        }
+    }
+
+    #[test]
+    fn reject_too_many_components() {
+        // components with byte offsets and MORE_COMPONENTS set on all but the last
+        let composite = |num_components: usize| {
+            let mut composite_stream = Vec::new();
+            for index in 0..num_components {
+                let more_components = if index + 1 < num_components {
+                    0x0020
+                } else {
+                    0
+                };
+                composite_stream.put_u16(0x0002 | more_components);
+                composite_stream.put_slice(&[0, 0, 1, 2]);
+            }
+            TransformedGlyf {
+                num_glyphs: 2,
+                n_contour_stream: vec![0, 0, 0xFF, 0xFF],
+                composite_stream,
+                bbox_glyphs: vec![1],
+                bbox_stream: vec![0, 1, 0, 2, 0, 3, 0, 4],
+                ..Default::default()
+            }
+            .to_bytes()
+        };
+        assert!(decode_glyf_table(&composite(MAX_COMPONENTS_PER_GLYPH)).is_ok());
+        assert!(matches!(
+            decode_glyf_table(&composite(MAX_COMPONENTS_PER_GLYPH + 1)),
+            Err(GlyfDecoderError::TooManyComponents(1))
+        ));
+    }
+
+    #[test]
+    fn instructions_past_end_of_stream() {
+        // one component with instructions, whose length (0xFFFF) is more than the one byte of
+        // instructions there is
+        let mut composite_stream = Vec::new();
+        composite_stream.put_u16(0x0100);
+        composite_stream.put_slice(&[0, 0, 1, 2]);
+        let table = TransformedGlyf {
+            num_glyphs: 1,
+            n_contour_stream: vec![0xFF, 0xFF],
+            glyph_stream: vec![253, 0xFF, 0xFF],
+            composite_stream,
+            bbox_glyphs: vec![0],
+            bbox_stream: vec![0, 1, 0, 2, 0, 3, 0, 4],
+            instruction_stream: vec![0x2C],
+            ..Default::default()
+        }
+        .to_bytes();
+        let mut decoder = Woff2GlyfDecoder::new(&table).unwrap();
+        let mut glyph = Vec::new();
+        assert!(matches!(
+            decoder.parse_next_glyph(0, &mut glyph),
+            Err(GlyfDecoderError::Truncated)
+        ));
+        // the header and the component, but not the instruction length
+        assert_eq!(glyph.len(), 10 + 6);
+
+        // the same for a simple glyph
+        let table = TransformedGlyf {
+            num_glyphs: 1,
+            n_contour_stream: vec![0, 1],
+            n_points_stream: vec![1],
+            flag_stream: vec![0],
+            glyph_stream: vec![5, 2],
+            instruction_stream: vec![0x2C],
+            ..Default::default()
+        }
+        .to_bytes();
+        let mut decoder = Woff2GlyfDecoder::new(&table).unwrap();
+        let mut glyph = Vec::new();
+        assert!(matches!(
+            decoder.parse_next_glyph(0, &mut glyph),
+            Err(GlyfDecoderError::Truncated)
+        ));
+        assert!(glyph.is_empty());
+    }
+
+    #[test]
+    fn reject_invalid_composite_flags() {
+        // a reserved bit, then both a scale and an x and y scale
+        for flags in [0x0012, 0x8002, 0x004A] {
+            let table = composite_glyph(flags, &[0; 8]);
+            assert!(matches!(
+                decode_glyf_table(&table),
+                Err(GlyfDecoderError::InvalidCompositeFlags(f)) if f == flags
+            ));
+        }
+    }
+
+    #[test]
+    fn keep_write_errors() {
+        use std::error::Error;
+
+        let error = GlyfDecoderError::from(std::io::Error::other("disk full"));
+        assert!(matches!(error, GlyfDecoderError::Write(_)));
+        assert_eq!(error.source().unwrap().to_string(), "disk full");
+        assert!(matches!(
+            GlyfDecoderError::from(safer_bytes::error::Truncated),
+            GlyfDecoderError::Truncated
+        ));
+    }
+
+    #[test]
+    fn keep_read_errors() {
+        use std::{error::Error, io::Read};
+
+        struct FailingReader;
+        impl Read for FailingReader {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("connection reset"))
+            }
+        }
+        // the start of a table, then a failed read
+        let table = glyphs_with_instructions(2, 4, 0);
+        let failing = Read::chain(&table[..10], FailingReader);
+        let error = decode_glyf_table_from_reader(failing).err().unwrap();
+        assert!(matches!(error, GlyfDecoderError::Read(_)));
+        assert_eq!(error.source().unwrap().to_string(), "connection reset");
+
+        let decoded = decode_glyf_table_from_reader(&table[..]).unwrap();
+        assert_eq!(decoded.glyf, decode_glyf_table(&table).unwrap().glyf);
+    }
+
+    #[test]
+    fn reject_unknown_index_format() {
+        for index_format in [2, u16::MAX] {
+            let table = TransformedGlyf {
+                index_format,
+                ..Default::default()
+            }
+            .to_bytes();
+            assert!(matches!(
+                decode_glyf_table(&table),
+                Err(GlyfDecoderError::InvalidIndexFormat(format)) if format == index_format
+            ));
+        }
+    }
+
+    #[test]
+    fn look_up_glyph_ranges() {
+        let short_loca = [0, 0, 0, 5, 0, 5, 0, 4];
+        assert_eq!(glyph_range(&short_loca, 0, 0), Some(0..10));
+        assert_eq!(glyph_range(&short_loca, 0, 1), Some(10..10));
+        assert_eq!(glyph_range(&short_loca, 0, 2), None);
+        assert_eq!(glyph_range(&short_loca, 0, 3), None);
+        // the same bytes read as long offsets
+        assert_eq!(glyph_range(&short_loca, 1, 0), Some(5..0x0005_0004));
+        let long_loca = [0, 0, 0, 0, 0, 1, 0, 0];
+        assert_eq!(glyph_range(&long_loca, 1, 0), Some(0..0x10000));
+        assert_eq!(glyph_range(&long_loca, 1, 1), None);
+    }
+
+    #[test]
+    fn reject_overflowing_stream_sizes() {
+        let table = TransformedGlyf {
+            num_glyphs: 1,
+            n_contour_stream: vec![0, 0],
+            ..Default::default()
+        }
+        .to_bytes();
+        // sizes that add up to the real stream length modulo 2^32, so that on 32-bit targets only
+        // checking the end of the last stream would let them through
+        for sizes in [[u32::MAX, 3], [3, u32::MAX], [0x8000_0000, 0x8000_0002]] {
+            let mut table = table.clone();
+            table[8..12].copy_from_slice(&sizes[0].to_be_bytes());
+            table[12..16].copy_from_slice(&sizes[1].to_be_bytes());
+            assert!(matches!(
+                decode_glyf_table(&table),
+                Err(GlyfDecoderError::Truncated)
+            ));
+        }
+    }
+
+    #[test]
+    fn reject_streams_past_end_of_table() {
+        let mut table = TransformedGlyf {
+            num_glyphs: 1,
+            n_contour_stream: vec![0, 0],
+            ..Default::default()
+        }
+        .to_bytes();
+        // a flag stream that runs past the end, followed by streams that claim to fit
+        table[16..20].copy_from_slice(&100u32.to_be_bytes());
+        assert!(matches!(
+            decode_glyf_table(&table),
+            Err(GlyfDecoderError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn bbox_stream_smaller_than_bitmap() {
+        let mut table = TransformedGlyf {
+            num_glyphs: 1,
+            n_contour_stream: vec![0, 0],
+            ..Default::default()
+        }
+        .to_bytes();
+        // bbox stream size (which includes the 4-byte bitmap) claims 0 bytes
+        table[28..32].copy_from_slice(&0u32.to_be_bytes());
+        assert!(matches!(
+            decode_glyf_table(&table),
+            Err(GlyfDecoderError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn reject_data_after_streams() {
+        let mut table = TransformedGlyf {
+            num_glyphs: 1,
+            n_contour_stream: vec![0, 0],
+            ..Default::default()
+        }
+        .to_bytes();
+        table.extend_from_slice(&[0; 4]);
+        assert!(matches!(
+            decode_glyf_table(&table),
+            Err(GlyfDecoderError::ExtraData)
+        ));
+        assert!(matches!(glyphs(&table), Err(GlyfDecoderError::ExtraData)));
+    }
+
+    #[test]
+    fn reject_too_many_points() {
+        let table = TransformedGlyf {
+            num_glyphs: 1,
+            n_contour_stream: vec![0, 1],
+            n_points_stream: vec![3],
+            flag_stream: vec![0; 3],
+            glyph_stream: vec![5, 5, 5, 0],
+            ..Default::default()
+        }
+        .to_bytes();
+        assert!(decode_glyf_table_with_max_points(&table, 3).is_ok());
+        assert!(matches!(
+            decode_glyf_table_with_max_points(&table, 2),
+            Err(GlyfDecoderError::TooManyPoints {
+                glyph_index: 0,
+                limit: 2
+            })
+        ));
+
+        // the total of two contours doesn't fit in the `u16` end points
+        let table = TransformedGlyf {
+            num_glyphs: 1,
+            n_contour_stream: vec![0, 2],
+            n_points_stream: vec![253, 0x9C, 0x40, 253, 0x9C, 0x40],
+            flag_stream: vec![0; 40000],
+            glyph_stream: vec![5; 40000],
+            ..Default::default()
+        }
+        .to_bytes();
+        assert!(matches!(
+            decode_glyf_table(&table),
+            Err(GlyfDecoderError::TooManyPoints {
+                glyph_index: 0,
+                limit: u16::MAX
+            })
+        ));
+    }
+
+    #[test]
+    fn stream_layout() {
+        let table = TransformedGlyf {
+            num_glyphs: 2,
+            index_format: 1,
+            n_contour_stream: vec![0, 1, 0, 0],
+            n_points_stream: vec![3],
+            flag_stream: vec![0; 3],
+            glyph_stream: vec![5, 5, 5, 1],
+            instruction_stream: vec![0x2C],
+            overlap_glyphs: Some(vec![0]),
+            ..Default::default()
+        }
+        .to_bytes();
+        let layout = GlyfStreamLayout::from_slice(&table).unwrap();
+        assert_eq!(
+            layout,
+            GlyfStreamLayout {
+                num_glyphs: 2,
+                index_format: 1,
+                n_contour_stream: 36..40,
+                n_points_stream: 40..41,
+                flag_stream: 41..44,
+                glyph_stream: 44..48,
+                composite_stream: 48..48,
+                bbox_bitmap: 48..52,
+                bbox_stream: 52..52,
+                instruction_stream: 52..53,
+                overlap_bitmap: Some(53..57),
+            }
+        );
+        assert_eq!(layout.overlap_bitmap.unwrap().end, table.len());
+        assert!(decode_glyf_table(&table).is_ok());
+    }
+}
@@ -0,0 +1,142 @@
+//! Reconstruction of transformed `hmtx` tables
+//!
+//! Spec: https://www.w3.org/TR/WOFF2/#hmtx_table_format
+
+use bytes::BufMut;
+use safer_bytes::{error::Truncated, SafeBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum HmtxDecoderError {
+    #[error("Stream truncated")]
+    Truncated,
+    #[error("Reserved flags set")]
+    ReservedFlags,
+    #[error("Invalid number of horizontal metrics")]
+    InvalidNumberOfHMetrics,
+    #[error("Extra Data")]
+    ExtraData,
+}
+
+impl From<Truncated> for HmtxDecoderError {
+    fn from(_: Truncated) -> Self {
+        HmtxDecoderError::Truncated
+    }
+}
+
+/// The `lsb` array for proportional glyphs was removed
+const PROPORTIONAL_LSBS_REMOVED: u8 = 0x01;
+/// The `leftSideBearing` array for monospaced glyphs was removed
+const MONOSPACE_LSBS_REMOVED: u8 = 0x02;
+
+/// Reconstructs an `hmtx` table, taking any removed left side bearings from the glyphs' `xMin`.
+///
+/// The number of glyphs is the length of `x_mins`. Flags `0x00` (neither array removed) aren't
+/// allowed by the spec, but some encoders emit them; the arrays are then just read through.
+pub fn decode_hmtx_table(
+    mut hmtx_table: &[u8],
+    number_of_h_metrics: u16,
+    x_mins: &[i16],
+) -> Result<Vec<u8>, HmtxDecoderError> {
+    let num_glyphs = x_mins.len();
+    let number_of_h_metrics = number_of_h_metrics as usize;
+    if number_of_h_metrics == 0 || number_of_h_metrics > num_glyphs {
+        return Err(HmtxDecoderError::InvalidNumberOfHMetrics);
+    }
+
+    let flags = hmtx_table.try_get_u8()?;
+    if flags & !(PROPORTIONAL_LSBS_REMOVED | MONOSPACE_LSBS_REMOVED) != 0 {
+        return Err(HmtxDecoderError::ReservedFlags);
+    }
+
+    let mut advance_widths = Vec::with_capacity(number_of_h_metrics);
+    for _ in 0..number_of_h_metrics {
+        advance_widths.push(hmtx_table.try_get_u16()?);
+    }
+
+    let mut output = Vec::with_capacity(4 * number_of_h_metrics + 2 * num_glyphs);
+    let mut lsbs = Vec::with_capacity(number_of_h_metrics);
+    for &x_min in &x_mins[..number_of_h_metrics] {
+        lsbs.push(if flags & PROPORTIONAL_LSBS_REMOVED == 0 {
+            hmtx_table.try_get_i16()?
+        } else {
+            x_min
+        });
+    }
+    for (advance_width, lsb) in advance_widths.into_iter().zip(lsbs) {
+        output.put_u16(advance_width);
+        output.put_i16(lsb);
+    }
+    for &x_min in &x_mins[number_of_h_metrics..] {
+        output.put_i16(if flags & MONOSPACE_LSBS_REMOVED == 0 {
+            hmtx_table.try_get_i16()?
+        } else {
+            x_min
+        });
+    }
+
+    if !hmtx_table.is_empty() {
+        return Err(HmtxDecoderError::ExtraData);
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_hmtx_table, HmtxDecoderError};
+
+    const X_MINS: [i16; 3] = [10, 20, -30];
+    /// advance widths 500 and 600, lsbs 10 and 20, then monospaced lsb -30
+    const HMTX: [u8; 10] = [0x01, 0xF4, 0x00, 0x0A, 0x02, 0x58, 0x00, 0x14, 0xFF, 0xE2];
+
+    #[test]
+    fn all_lsbs_removed() {
+        let table = [0x03, 0x01, 0xF4, 0x02, 0x58];
+        assert_eq!(decode_hmtx_table(&table, 2, &X_MINS).unwrap(), HMTX);
+    }
+
+    #[test]
+    fn proportional_lsbs_removed() {
+        let table = [0x01, 0x01, 0xF4, 0x02, 0x58, 0xFF, 0xE2];
+        assert_eq!(decode_hmtx_table(&table, 2, &X_MINS).unwrap(), HMTX);
+    }
+
+    #[test]
+    fn monospace_lsbs_removed() {
+        let table = [0x02, 0x01, 0xF4, 0x02, 0x58, 0x00, 0x0A, 0x00, 0x14];
+        assert_eq!(decode_hmtx_table(&table, 2, &X_MINS).unwrap(), HMTX);
+    }
+
+    #[test]
+    fn identity_transform_is_read_through() {
+        let table = [
+            0x00, 0x01, 0xF4, 0x02, 0x58, 0x00, 0x0A, 0x00, 0x14, 0xFF, 0xE2,
+        ];
+        // the explicit lsbs are used even if they don't match the glyphs
+        assert_eq!(decode_hmtx_table(&table, 2, &[0; 3]).unwrap(), HMTX);
+    }
+
+    #[test]
+    fn reject_bad_tables() {
+        assert!(matches!(
+            decode_hmtx_table(&[0x04, 0x01, 0xF4, 0x02, 0x58], 2, &X_MINS),
+            Err(HmtxDecoderError::ReservedFlags)
+        ));
+        assert!(matches!(
+            decode_hmtx_table(&[0x03, 0x01, 0xF4], 2, &X_MINS),
+            Err(HmtxDecoderError::Truncated)
+        ));
+        assert!(matches!(
+            decode_hmtx_table(&[0x03, 0x01, 0xF4, 0x02, 0x58, 0x00], 2, &X_MINS),
+            Err(HmtxDecoderError::ExtraData)
+        ));
+        assert!(matches!(
+            decode_hmtx_table(&[0x03], 0, &X_MINS),
+            Err(HmtxDecoderError::InvalidNumberOfHMetrics)
+        ));
+        assert!(matches!(
+            decode_hmtx_table(&[0x03], 4, &X_MINS),
+            Err(HmtxDecoderError::InvalidNumberOfHMetrics)
+        ));
+    }
+}
@@ -0,0 +1,371 @@
+//! Diagnostics for WOFF2 files
+//!
+//! [`inspect`] reports problems that don't stop a font from being decoded, but which suggest that
+//! the encoder that produced it doesn't follow the spec's recommendations, or that the font itself
+//! is broken.
+
+use bytes::Buf;
+use four_cc::FourCC;
+use thiserror::Error;
+
+use crate::{
+    decode::{decompress_woff2, find_glyf_loca, DecodeError, DecodeOptions, DecompressedWoff2},
+    glyf_decoder::{glyph_range, glyphs},
+    tag::display_tag,
+    woff2::{
+        header::Woff2Header,
+        table_directory::{
+            Woff2TableDirectory, WriteTablesError, CMAP_TAG, GLYF_TAG, HEAD_TAG, LOCA_TAG,
+        },
+    },
+};
+
+/// A problem found by [`inspect`] that doesn't prevent decoding.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Woff2Warning {
+    #[error(
+        "Table `{}` appears after `{}` in the table directory",
+        display_tag(*.tag),
+        display_tag(*.previous)
+    )]
+    TableOutOfOrder {
+        #[cfg_attr(feature = "serde", serde(serialize_with = "crate::tag::serialize_tag"))]
+        tag: FourCC,
+        #[cfg_attr(feature = "serde", serde(serialize_with = "crate::tag::serialize_tag"))]
+        previous: FourCC,
+    },
+    #[error("Glyph 0 (`.notdef`) has no outline")]
+    EmptyNotdef,
+    #[error("The font has no `head` table")]
+    MissingHead,
+    #[error("The font has no `cmap` table, so text can't be mapped to its glyphs")]
+    MissingCmap,
+    #[error(
+        "The `loca` table is transformed but the `glyf` table isn't, so it has to be rebuilt from the glyphs"
+    )]
+    LoneTransformedLoca,
+    #[error(
+        "Table `{}` (index {}) isn't used by any font in the collection",
+        display_tag(*.tag),
+        .index
+    )]
+    UnreferencedTable {
+        index: u16,
+        #[cfg_attr(feature = "serde", serde(serialize_with = "crate::tag::serialize_tag"))]
+        tag: FourCC,
+    },
+}
+
+/// The result of inspecting a WOFF2 file.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Woff2Report {
+    pub header: Woff2Header,
+    pub table_directory: Woff2TableDirectory,
+    pub warnings: Vec<Woff2Warning>,
+    /// The length of the brotli stream holding the table data, as read by the decompressor. This
+    /// leaves out any zero padding that's counted in the header's `total_compressed_size`.
+    pub compressed_size: usize,
+    /// The length of the table data once decompressed
+    pub decompressed_size: usize,
+}
+
+impl Woff2Report {
+    /// Returns how many times larger the table data is once decompressed.
+    pub fn compression_ratio(&self) -> f64 {
+        self.decompressed_size as f64 / self.compressed_size as f64
+    }
+}
+
+/// Reads and decompresses the WOFF2 font in `input_buffer`, collecting warnings about anything
+/// unusual.
+///
+/// Errors are only returned for problems that would also make decoding fail.
+pub fn inspect(input_buffer: &mut impl Buf) -> Result<Woff2Report, DecodeError> {
+    let decompressed = decompress_woff2(input_buffer, &DecodeOptions::default())?;
+
+    let mut warnings = Vec::new();
+    check_table_order(&decompressed.table_directory, &mut warnings);
+    let table_directory = &decompressed.table_directory;
+    if decompressed.collection_header.is_none() {
+        for (tag, warning) in [
+            (HEAD_TAG, Woff2Warning::MissingHead),
+            (CMAP_TAG, Woff2Warning::MissingCmap),
+        ] {
+            if table_directory.find(tag).is_none() {
+                warnings.push(warning);
+            }
+        }
+    }
+    let transformed = |tag| table_directory.find(tag).map(|table| table.transformed);
+    if transformed(LOCA_TAG) == Some(true) && transformed(GLYF_TAG) == Some(false) {
+        // glyph 0 can't be found without `loca`
+        warnings.push(Woff2Warning::LoneTransformedLoca);
+    } else {
+        check_notdef(&decompressed, &mut warnings)?;
+    }
+    check_collection_tables(&decompressed, &mut warnings);
+
+    Ok(Woff2Report {
+        header: decompressed.header,
+        table_directory: decompressed.table_directory,
+        warnings,
+        compressed_size: decompressed.compressed_size,
+        decompressed_size: decompressed.decompressed_tables.len(),
+    })
+}
+
+/// Inspects the WOFF2 font in `input` like [`inspect`], and returns the report as JSON.
+///
+/// Tags are written as strings, with any non-printable bytes escaped (e.g. the TrueType flavor is
+/// `\x00\x01\x00\x00`).
+#[cfg(feature = "serde")]
+pub fn describe_json(mut input: &[u8]) -> Result<String, DecodeError> {
+    let report = inspect(&mut input)?;
+    Ok(serde_json::to_string(&report).expect("the report always serializes"))
+}
+
+/// Checks that tables are sorted by tag, as the reference encoder writes them.
+///
+/// `loca` may be moved up to directly follow `glyf`, which is where the reference encoder puts it
+/// when the two are transformed.
+fn check_table_order(table_directory: &Woff2TableDirectory, warnings: &mut Vec<Woff2Warning>) {
+    let mut previous: Option<FourCC> = None;
+    let mut after_glyf = false;
+    for table in &table_directory.tables {
+        let moved_loca = after_glyf && table.tag == LOCA_TAG;
+        after_glyf = table.tag == GLYF_TAG;
+        if moved_loca {
+            // compare the next table against `glyf` instead
+            continue;
+        }
+        if let Some(previous) = previous {
+            if table.tag.0 <= previous.0 {
+                warnings.push(Woff2Warning::TableOutOfOrder {
+                    tag: table.tag,
+                    previous,
+                });
+            }
+        }
+        previous = Some(table.tag);
+    }
+}
+
+/// Checks that glyph 0, which OpenType requires to be `.notdef`, has an outline.
+///
+/// Only the first `glyf` table of a collection is checked.
+fn check_notdef(
+    decompressed: &DecompressedWoff2,
+    warnings: &mut Vec<Woff2Warning>,
+) -> Result<(), DecodeError> {
+    let table_directory = &decompressed.table_directory;
+    let Some(glyf) = table_directory.find(GLYF_TAG) else {
+        return Ok(());
+    };
+    let notdef_length = if glyf.transformed {
+        // glyph 0 comes first in the streams, so there's no need to reconstruct `loca`, which may
+        // not have the index format given in `head` anyway
+        let mut glyphs = glyphs(&decompressed.decompressed_tables[glyf.get_source_range()])
+            .map_err(WriteTablesError::from)?;
+        match glyphs.next() {
+            Some(notdef) => notdef.map_err(WriteTablesError::from)?.data.len(),
+            None => 0,
+        }
+    } else {
+        // `indexToLocFormat`; without it the font won't decode anyway
+        let index_format = match table_directory
+            .find(HEAD_TAG)
+            .and_then(|head| decompressed.decompressed_tables[head.get_source_range()].get(50..52))
+        {
+            Some(index_format) => u16::from_be_bytes([index_format[0], index_format[1]]),
+            None => return Ok(()),
+        };
+        let (glyf, loca) = find_glyf_loca(decompressed)?;
+        glyph_range(&loca, index_format, 0)
+            .and_then(|range| glyf.get(range))
+            .map_or(0, <[u8]>::len)
+    };
+    if notdef_length == 0 {
+        warnings.push(Woff2Warning::EmptyNotdef);
+    }
+    Ok(())
+}
+
+/// Checks that every table of a collection belongs to at least one of its fonts.
+fn check_collection_tables(decompressed: &DecompressedWoff2, warnings: &mut Vec<Woff2Warning>) {
+    let collection_header = match &decompressed.collection_header {
+        Some(collection_header) => collection_header,
+        None => return,
+    };
+    let tables = &decompressed.table_directory.tables;
+    let mut referenced = vec![false; tables.len()];
+    for font in &collection_header.fonts {
+        for &index in &font.table_indices {
+            referenced[index as usize] = true;
+        }
+    }
+    for (index, table) in tables.iter().enumerate() {
+        if !referenced[index] {
+            warnings.push(Woff2Warning::UnreferencedTable {
+                index: index as u16,
+                tag: table.tag,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use four_cc::FourCC;
+
+    use crate::test_resources::{
+        woff2_collection_with_tables, woff2_with_tables, woff2_with_transformed_loca,
+        FONTAWESOME_REGULAR_400, LATO_V22_LATIN_REGULAR, TEST_KERN_ONE_CFF,
+    };
+
+    use super::{inspect, Woff2Warning};
+
+    #[test]
+    fn sample_fonts_have_no_warnings() {
+        // the CFF font has no `glyf` table to check
+        for font in [LATO_V22_LATIN_REGULAR, TEST_KERN_ONE_CFF] {
+            let report = inspect(&mut Cursor::new(font)).unwrap();
+            assert!(report.warnings.is_empty());
+        }
+    }
+
+    #[test]
+    fn compression_ratio() {
+        let report = inspect(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        assert_eq!(
+            report.compressed_size,
+            report.header.total_compressed_size as usize
+        );
+        assert_eq!(
+            report.decompressed_size,
+            report.table_directory.uncompressed_length as usize
+        );
+        assert!(report.compression_ratio() > 1.0);
+
+        // zero padding counted in the stream's declared size isn't part of the brotli stream;
+        // Lato's stream is followed by one byte of padding
+        let mut font = LATO_V22_LATIN_REGULAR.to_vec();
+        font.splice(font.len() - 1..font.len() - 1, [0; 3]);
+        let declared_size = u32::from_be_bytes(font[20..24].try_into().unwrap()) + 3;
+        font[20..24].copy_from_slice(&declared_size.to_be_bytes());
+        font.resize(font.len() + 1, 0);
+        let length = font.len() as u32;
+        font[8..12].copy_from_slice(&length.to_be_bytes());
+        let padded_report = inspect(&mut Cursor::new(font)).unwrap();
+        assert_eq!(
+            padded_report.header.total_compressed_size,
+            report.header.total_compressed_size + 3
+        );
+        assert_eq!(padded_report.compressed_size, report.compressed_size);
+
+        // stored without compression, the stream is slightly larger than the data
+        let report = inspect(&mut Cursor::new(woff2_with_tables(&[b"head"]))).unwrap();
+        assert_eq!((report.compressed_size, report.decompressed_size), (8, 4));
+        assert_eq!(report.compression_ratio(), 0.5);
+    }
+
+    #[test]
+    fn warn_on_empty_notdef() {
+        let report = inspect(&mut Cursor::new(FONTAWESOME_REGULAR_400)).unwrap();
+        assert_eq!(report.warnings, [Woff2Warning::EmptyNotdef]);
+    }
+
+    #[test]
+    fn warn_on_unsorted_tables() {
+        let font = woff2_with_tables(&[b"head", b"cmap", b"glyf", b"loca", b"post", b"name"]);
+        let report = inspect(&mut Cursor::new(font)).unwrap();
+        assert_eq!(
+            report.warnings,
+            [
+                Woff2Warning::TableOutOfOrder {
+                    tag: FourCC(*b"cmap"),
+                    previous: FourCC(*b"head"),
+                },
+                Woff2Warning::TableOutOfOrder {
+                    tag: FourCC(*b"name"),
+                    previous: FourCC(*b"post"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn warn_on_missing_head() {
+        let font = woff2_with_tables(&[b"CFF ", b"cmap", b"name"]);
+        let report = inspect(&mut Cursor::new(font)).unwrap();
+        assert_eq!(report.warnings, [Woff2Warning::MissingHead]);
+    }
+
+    #[test]
+    fn warn_on_lone_transformed_loca() {
+        let font = woff2_with_transformed_loca(
+            &[(b"cmap", &[0; 4]), (b"glyf", &[0; 4]), (b"head", &[0; 4])],
+            4,
+        );
+        let report = inspect(&mut Cursor::new(font)).unwrap();
+        assert_eq!(report.warnings, [Woff2Warning::LoneTransformedLoca]);
+    }
+
+    #[test]
+    fn warn_on_missing_cmap() {
+        let font = woff2_with_tables(&[b"CFF ", b"head", b"name"]);
+        let report = inspect(&mut Cursor::new(font)).unwrap();
+        assert_eq!(report.warnings, [Woff2Warning::MissingCmap]);
+    }
+
+    #[test]
+    fn warn_on_unreferenced_collection_tables() {
+        let tags = [b"OS/2", b"head", b"name", b"post"];
+        let font = woff2_collection_with_tables(&tags, &[&[0, 1, 2], &[0, 1, 2]]);
+        let report = inspect(&mut Cursor::new(font)).unwrap();
+        assert_eq!(
+            report.warnings,
+            [Woff2Warning::UnreferencedTable {
+                index: 3,
+                tag: FourCC(*b"post"),
+            }]
+        );
+
+        let font = woff2_collection_with_tables(&tags, &[&[0, 1, 2], &[0, 1, 3]]);
+        let report = inspect(&mut Cursor::new(font)).unwrap();
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn reject_undecodable_fonts() {
+        let mut font = woff2_with_tables(&[b"head", b"name"]);
+        // truncate the compressed stream
+        font.truncate(font.len() - 8);
+        assert!(inspect(&mut Cursor::new(font)).is_err());
+    }
+
+    #[test]
+    fn allow_loca_after_glyf() {
+        let font = woff2_with_tables(&[b"cmap", b"glyf", b"loca", b"head", b"hmtx", b"maxp"]);
+        let report = inspect(&mut Cursor::new(font)).unwrap();
+        assert!(report.warnings.is_empty());
+
+        let font = woff2_with_tables(&[b"cmap", b"glyf", b"head", b"loca", b"maxp"]);
+        let report = inspect(&mut Cursor::new(font)).unwrap();
+        assert!(report.warnings.is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn describe_font_as_json() {
+        let json = super::describe_json(LATO_V22_LATIN_REGULAR).unwrap();
+        let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(report["header"]["signature"], "wOF2");
+        let glyf = &report["table_directory"]["tables"][7];
+        assert_eq!(glyf["tag"], "glyf");
+        assert_eq!(glyf["transformed"], true);
+        assert_eq!(report["warnings"], serde_json::json!([]));
+    }
+}
@@ -1,14 +1,25 @@
 #![doc = include_str!("../readme.md")]
+#[macro_use]
+mod logging;
+
+pub mod brotli_backend;
+pub mod checksum;
 pub mod decode;
+#[cfg(feature = "backend-brotli")]
+pub mod encode;
+pub mod glyf_decoder;
+pub mod inspect;
+pub mod sfnt;
+pub mod woff2;
 
 mod buffer_util;
-mod checksum;
-mod glyf_decoder;
+mod hmtx_decoder;
 mod magic_numbers;
+mod tag;
 mod ttf_header;
-mod woff2;
 
 #[cfg(test)]
 mod test_resources;
 
 pub use decode::convert_woff2_to_ttf;
+pub use four_cc::FourCC;
@@ -0,0 +1,17 @@
+//! Logging through the `log` crate with the `logging` feature, and nothing without it
+
+/// Logs a debug message with the `logging` feature.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "logging")]
+        log::debug!($($arg)*);
+    };
+}
+
+/// Logs a warning with the `logging` feature.
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "logging")]
+        log::warn!($($arg)*);
+    };
+}
@@ -0,0 +1,169 @@
+//! Assembling OpenType fonts from their tables
+
+use four_cc::FourCC;
+
+use crate::{
+    buffer_util::pad_to_multiple_of_four,
+    checksum::{
+        calculate_checksum, calculate_font_checksum_adjustment, set_checksum_adjustment,
+        ChecksumError,
+    },
+    ttf_header::{calculate_header_size, TableDirectory, TableRecord},
+    woff2::table_directory::HEAD_TAG,
+};
+
+/// Builds an OpenType font with the given `flavor` (sfnt version) out of `tables`.
+///
+/// The tables are sorted by tag and padded to a multiple of four bytes, and the table directory
+/// gets their checksums. If there is a valid `head` table, its `checkSumAdjustment` is set so that
+/// the font's checksum comes out right; a missing or malformed `head` table is left alone.
+pub fn finalize_sfnt(mut tables: Vec<(FourCC, Vec<u8>)>, flavor: FourCC) -> Vec<u8> {
+    tables.sort_unstable_by_key(|(tag, _)| tag.0);
+    let header_end = calculate_header_size(tables.len());
+    let total_size = header_end
+        + tables
+            .iter()
+            .map(|(_, data)| (data.len() + 3) & !3)
+            .sum::<usize>();
+    let mut font = Vec::with_capacity(total_size);
+    font.resize(header_end, 0);
+
+    let mut table_records = Vec::with_capacity(tables.len());
+    for (tag, mut data) in tables {
+        if tag == HEAD_TAG {
+            // the whole-font checksum is calculated with the adjustment zeroed; if this fails
+            // `write_table_directory` won't touch the table either
+            let _ = set_checksum_adjustment(&mut data, 0);
+        }
+        table_records.push(TableRecord {
+            tag,
+            checksum: calculate_checksum(&data),
+            offset: font.len().try_into().expect("font larger than 4 GiB"),
+            length: data.len().try_into().expect("table larger than 4 GiB"),
+        });
+        font.extend_from_slice(&data);
+        pad_to_multiple_of_four(&mut font);
+    }
+
+    let _ = write_table_directory(&mut font, flavor, table_records);
+    font
+}
+
+/// Writes the table directory for `table_records` at the start of `font`, then sets
+/// `checkSumAdjustment` in the `head` table.
+///
+/// `font` must already contain the tables. Returns `Ok(false)` if there is no `head` table.
+pub(crate) fn write_table_directory(
+    font: &mut [u8],
+    flavor: FourCC,
+    table_records: Vec<TableRecord>,
+) -> Result<bool, ChecksumError> {
+    let table_directory = TableDirectory::new(flavor, table_records);
+    table_directory.write_to_buf(&mut &mut font[..]);
+    let head_table_record = match table_directory.find_table(HEAD_TAG) {
+        Some(head_table_record) => head_table_record,
+        None => return Ok(false),
+    };
+    let head_table = &mut font[head_table_record.get_range()];
+    // check the magic number before touching the rest of the font
+    set_checksum_adjustment(head_table, 0)?;
+    let checksum_adjustment = calculate_font_checksum_adjustment(font);
+    debug!(
+        "Setting `checkSumAdjustment` to {:#010X}",
+        checksum_adjustment
+    );
+    set_checksum_adjustment(
+        &mut font[head_table_record.get_range()],
+        checksum_adjustment,
+    )?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use four_cc::FourCC;
+    use ttf_parser::{RawFace, Tag};
+
+    use super::finalize_sfnt;
+    use crate::{
+        checksum::calculate_checksum,
+        decode::convert_woff2_to_ttf,
+        test_resources::{LATO_V22_LATIN_REGULAR, TEST_KERN_ONE_CFF},
+    };
+
+    /// Reads the tables out of `ttf`, in the order they're stored in the file.
+    fn read_tables(ttf: &[u8]) -> Vec<(FourCC, Vec<u8>)> {
+        let num_tables = u16::from_be_bytes([ttf[4], ttf[5]]) as usize;
+        let mut records: Vec<_> = ttf[12..12 + 16 * num_tables]
+            .chunks_exact(16)
+            .map(|record| {
+                let tag = FourCC(record[..4].try_into().unwrap());
+                let offset = u32::from_be_bytes(record[8..12].try_into().unwrap()) as usize;
+                let length = u32::from_be_bytes(record[12..16].try_into().unwrap()) as usize;
+                (offset, tag, ttf[offset..offset + length].to_vec())
+            })
+            .collect();
+        records.sort_unstable_by_key(|&(offset, ..)| offset);
+        records
+            .into_iter()
+            .map(|(_, tag, data)| (tag, data))
+            .collect()
+    }
+
+    #[test]
+    fn rebuild_decoded_font() {
+        // the decoder also writes the tables in tag order, since there's no `glyf` and `loca`
+        let ttf = convert_woff2_to_ttf(&mut Cursor::new(TEST_KERN_ONE_CFF)).unwrap();
+        let flavor = FourCC(ttf[..4].try_into().unwrap());
+        let mut tables = read_tables(&ttf);
+        assert_eq!(ttf, finalize_sfnt(tables.clone(), flavor));
+
+        // the input order doesn't matter
+        tables.reverse();
+        assert_eq!(ttf, finalize_sfnt(tables, flavor));
+    }
+
+    #[test]
+    fn fix_checksums_of_edited_font() {
+        let ttf = convert_woff2_to_ttf(&mut Cursor::new(LATO_V22_LATIN_REGULAR)).unwrap();
+        let mut tables = read_tables(&ttf);
+        tables.retain(|(tag, _)| &tag.0 != b"GPOS");
+        let (_, head) = tables
+            .iter_mut()
+            .find(|(tag, _)| &tag.0 == b"head")
+            .unwrap();
+        head[8..12].copy_from_slice(&[0xDE, 0xAD, 0xBE, 0xEF]);
+
+        let rebuilt = finalize_sfnt(tables.clone(), FourCC(ttf[..4].try_into().unwrap()));
+        assert_eq!(0xB1B0AFBA, calculate_checksum(&rebuilt));
+        let mut rebuilt_tables = read_tables(&rebuilt);
+        for (tag, data) in tables.iter_mut().chain(&mut rebuilt_tables) {
+            if &tag.0 == b"head" {
+                data[8..12].fill(0);
+            }
+        }
+        tables.sort_unstable_by_key(|(tag, _)| tag.0);
+        assert_eq!(tables, rebuilt_tables);
+        let face = RawFace::from_slice(&rebuilt, 0).unwrap();
+        assert!(face.table(Tag::from_bytes(b"GPOS")).is_none());
+        assert!(face.table(Tag::from_bytes(b"glyf")).is_some());
+        assert!(ttf_parser::Face::from_slice(&rebuilt, 0).is_ok());
+    }
+
+    #[test]
+    fn pad_tables_without_head() {
+        let tables = vec![
+            (FourCC(*b"zzzz"), vec![1, 2, 3, 4, 5]),
+            (FourCC(*b"aaaa"), vec![6]),
+        ];
+        let font = finalize_sfnt(tables, FourCC(*b"true"));
+        assert_eq!(font.len(), 12 + 2 * 16 + 4 + 8);
+        assert_eq!(&font[..4], b"true");
+        assert_eq!(&font[12..16], b"aaaa");
+        assert_eq!(&font[44..], [6, 0, 0, 0, 1, 2, 3, 4, 5, 0, 0, 0]);
+        // offset and length of `zzzz`
+        assert_eq!(&font[36..44], [0, 0, 0, 48, 0, 0, 0, 5]);
+    }
+}
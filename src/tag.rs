@@ -0,0 +1,38 @@
+//! Formatting of table tags
+
+use four_cc::FourCC;
+
+/// Renders a tag as ASCII for messages, e.g. `glyf`, escaping any non-printable bytes.
+pub fn display_tag(tag: FourCC) -> String {
+    tag.0
+        .iter()
+        .flat_map(|&byte| std::ascii::escape_default(byte))
+        .map(char::from)
+        .collect()
+}
+
+/// Serializes a tag as a string, like [`display_tag`].
+#[cfg(feature = "serde")]
+pub fn serialize_tag<S: serde::Serializer>(tag: &FourCC, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&display_tag(*tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use four_cc::FourCC;
+
+    use super::display_tag;
+
+    #[test]
+    fn printable_tags() {
+        assert_eq!(display_tag(FourCC(*b"glyf")), "glyf");
+        assert_eq!(display_tag(FourCC(*b"cvt ")), "cvt ");
+        assert_eq!(display_tag(FourCC(*b"OS/2")), "OS/2");
+    }
+
+    #[test]
+    fn non_printable_tags() {
+        assert_eq!(display_tag(FourCC([0, 1, 0, 0])), "\\x00\\x01\\x00\\x00");
+        assert_eq!(display_tag(FourCC(*b"a\"\n\xff")), "a\\\"\\n\\xff");
+    }
+}
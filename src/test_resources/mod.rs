@@ -1,6 +1,272 @@
+use bytes::BufMut;
+
+use crate::buffer_util::BufMutExt;
+
 pub const LATO_V22_LATIN_REGULAR: &[u8] = include_bytes!("./lato-v22-latin-regular.woff2");
 
+// Lato re-encoded with a transformed `hmtx` table. The identity version has transform flags 0x00
+// (no arrays removed); the other has both left side bearing arrays removed.
+pub const LATO_V22_LATIN_REGULAR_HMTX_IDENTITY: &[u8] =
+    include_bytes!("./lato-v22-latin-regular-hmtx-identity.woff2");
+pub const LATO_V22_LATIN_REGULAR_HMTX_TRANSFORMED: &[u8] =
+    include_bytes!("./lato-v22-latin-regular-hmtx-transformed.woff2");
+
+// TestKERNOne.otf, a CFF font from the Unicode text rendering tests, converted to WOFF2
+// https://github.com/unicode-org/text-rendering-tests
+pub const TEST_KERN_ONE_CFF: &[u8] = include_bytes!("./TestKERNOne.woff2");
+
 // Font Awesome 5 v5.15.4
 // https://fontawesome.com/v5/download
 // https://use.fontawesome.com/releases/v5.15.4/fontawesome-free-5.15.4-web.zip
 pub const FONTAWESOME_REGULAR_400: &[u8] = include_bytes!("./fa-regular-400-v5.15.4.woff2");
+
+/// Wraps `data` in a brotli stream without compressing it, for building test fonts without
+/// needing a brotli encoder.
+pub fn stored_brotli_stream(data: &[u8]) -> Vec<u8> {
+    assert!(!data.is_empty());
+    let mut stream = Vec::with_capacity(data.len() + 4);
+    for (index, chunk) in data.chunks(1 << 16).enumerate() {
+        // ISLAST = 0, MNIBBLES = 4, MLEN - 1, ISUNCOMPRESSED = 1, then padding to a byte boundary;
+        // the first meta-block header is preceded by WBITS = 16, a single zero bit
+        let meta_block_header = ((chunk.len() as u32 - 1) << 3 | 1 << 19) << (index == 0) as u32;
+        stream.extend_from_slice(&meta_block_header.to_le_bytes()[..3]);
+        stream.extend_from_slice(chunk);
+    }
+    // ISLAST = 1, ISLASTEMPTY = 1
+    stream.push(0b11);
+    stream
+}
+
+/// Builds a WOFF2 file with null-transformed tables of four zero bytes each.
+pub fn woff2_with_tables(tags: &[&[u8; 4]]) -> Vec<u8> {
+    woff2_with_table_data(&zero_tables(tags))
+}
+
+/// Builds a WOFF2 file with null-transformed tables holding the given data.
+pub fn woff2_with_table_data(tables: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+    build_woff2(*b"\0\x01\0\0", tables, &[], None)
+}
+
+/// Builds a WOFF2 file like [`woff2_with_table_data`], but with an extra `loca` table after the
+/// others that's marked as transformed, like a broken encoder might write it. It has the given
+/// original length and no data.
+pub fn woff2_with_transformed_loca(tables: &[(&[u8; 4], &[u8])], loca_length: u32) -> Vec<u8> {
+    build_woff2(*b"\0\x01\0\0", tables, &[], Some(loca_length))
+}
+
+/// Builds a WOFF2 collection with null-transformed tables of four zero bytes each, and fonts made
+/// up of the tables at the given indices.
+pub fn woff2_collection_with_tables(tags: &[&[u8; 4]], fonts: &[&[u16]]) -> Vec<u8> {
+    let mut collection_directory = Vec::new();
+    collection_directory.put_u32(0x0001_0000);
+    collection_directory.put_255_u16(fonts.len() as u16);
+    for table_indices in fonts {
+        collection_directory.put_255_u16(table_indices.len() as u16);
+        collection_directory.put_slice(&[0, 1, 0, 0]);
+        for &index in *table_indices {
+            collection_directory.put_255_u16(index);
+        }
+    }
+    build_woff2(*b"ttcf", &zero_tables(tags), &collection_directory, None)
+}
+
+fn zero_tables<'a>(tags: &[&'a [u8; 4]]) -> Vec<(&'a [u8; 4], &'static [u8])> {
+    tags.iter().map(|&tag| (tag, &[0; 4][..])).collect()
+}
+
+fn build_woff2(
+    flavor: [u8; 4],
+    tables: &[(&[u8; 4], &[u8])],
+    collection_directory: &[u8],
+    transformed_loca_length: Option<u32>,
+) -> Vec<u8> {
+    let num_tables = tables.len() + transformed_loca_length.is_some() as usize;
+    let table_data: Vec<u8> = tables.iter().flat_map(|(_, data)| data.to_vec()).collect();
+    let stream = stored_brotli_stream(&table_data);
+    let mut font = Vec::new();
+    font.put_slice(b"wOF2");
+    font.put_slice(&flavor);
+    // length, filled in below
+    font.put_u32(0);
+    font.put_u16(num_tables as u16);
+    font.put_u16(0);
+    font.put_u32(12 + 20 * num_tables as u32);
+    font.put_u32(stream.len() as u32);
+    font.resize(48, 0);
+    for (tag, data) in tables {
+        let version = if matches!(*tag, b"glyf" | b"loca") {
+            0xC0
+        } else {
+            0
+        };
+        font.put_u8(0x3F | version);
+        font.put_slice(*tag);
+        font.put_base_128(data.len() as u32);
+    }
+    if let Some(loca_length) = transformed_loca_length {
+        // transform version 0 with a transform length of zero
+        font.put_u8(0x3F);
+        font.put_slice(b"loca");
+        font.put_base_128(loca_length);
+        font.put_base_128(0);
+    }
+    font.put_slice(collection_directory);
+    font.put_slice(&stream);
+    font.resize((font.len() + 3) & !3, 0);
+    let length = font.len() as u32;
+    font[8..12].copy_from_slice(&length.to_be_bytes());
+    font
+}
+
+/// The tables of a small embedded bitmap font with two 8 pixel wide glyphs.
+///
+/// `EBLC` has one strike, whose index subtable (format 1) points at glyphs 1 and 2 in `EBDT`
+/// (image format 1). `EBDT` is 17 bytes long, so it gets padded in the decoded font.
+pub fn bitmap_font_tables() -> Vec<(&'static [u8; 4], Vec<u8>)> {
+    let head = head_table();
+
+    let mut ebdt = Vec::new();
+    ebdt.put_u32(0x0002_0000);
+    // glyph 1: small metrics (height, width, bearingX, bearingY, advance) and one row
+    ebdt.put_slice(&[1, 8, 0, 1, 8, 0b1111_1111]);
+    // glyph 2: two rows
+    ebdt.put_slice(&[2, 8, 0, 2, 8, 0b1000_0001, 0b0111_1110]);
+
+    let line_metrics = [8, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let mut eblc = Vec::new();
+    eblc.put_u32(0x0002_0000);
+    // numSizes
+    eblc.put_u32(1);
+    // BitmapSize: indexSubTableArrayOffset, indexTablesSize, numberOfIndexSubTables, colorRef
+    eblc.put_u32(56);
+    eblc.put_u32(28);
+    eblc.put_u32(1);
+    eblc.put_u32(0);
+    eblc.put_slice(&line_metrics);
+    eblc.put_slice(&line_metrics);
+    // startGlyphIndex, endGlyphIndex, ppemX, ppemY, bitDepth, flags
+    eblc.put_u16(1);
+    eblc.put_u16(2);
+    eblc.put_slice(&[8, 8, 1, 1]);
+    // IndexSubTableArray: firstGlyphIndex, lastGlyphIndex, additionalOffsetToIndexSubtable
+    eblc.put_u16(1);
+    eblc.put_u16(2);
+    eblc.put_u32(8);
+    // IndexSubTable1: indexFormat, imageFormat, imageDataOffset, then sbitOffsets
+    eblc.put_u16(1);
+    eblc.put_u16(1);
+    eblc.put_u32(4);
+    for offset in [0, 6, 13] {
+        eblc.put_u32(offset);
+    }
+
+    let mut ebsc = Vec::new();
+    ebsc.put_u32(0x0002_0000);
+    ebsc.put_u32(1);
+    ebsc.put_slice(&line_metrics);
+    ebsc.put_slice(&line_metrics);
+    // ppemX, ppemY, substitutePpemX, substitutePpemY
+    ebsc.put_slice(&[16, 16, 8, 8]);
+
+    vec![
+        (b"EBDT", ebdt),
+        (b"EBLC", eblc),
+        (b"EBSC", ebsc),
+        (b"head", head),
+    ]
+}
+
+/// A `head` table with 1000 units per em and short `loca` offsets.
+fn head_table() -> Vec<u8> {
+    let mut head = vec![0; 54];
+    head[..4].copy_from_slice(&[0, 1, 0, 0]);
+    head[12..16].copy_from_slice(&[0x5F, 0x0F, 0x3C, 0xF5]);
+    head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+    head
+}
+
+/// The tables of a small variable font with a `wght` axis (100 to 900, default 400) and a named
+/// instance at 900.
+///
+/// Its one glyph is a triangle from (0, 0) to (100, 100), which `gvar` moves right by 50 units at
+/// the heaviest weight. `gvar` is 42 bytes long, so it gets padded in the decoded font. The tables
+/// are in tag order, except that `loca` comes right after `glyf`.
+pub fn variable_font_tables() -> Vec<(&'static [u8; 4], Vec<u8>)> {
+    let mut fvar = Vec::new();
+    // version, axesArrayOffset, reserved, axisCount, axisSize, instanceCount, instanceSize
+    fvar.put_u32(0x0001_0000);
+    fvar.put_u16(16);
+    fvar.put_u16(2);
+    fvar.put_u16(1);
+    fvar.put_u16(20);
+    fvar.put_u16(1);
+    fvar.put_u16(8);
+    // axis: tag, minValue, defaultValue, maxValue, flags, axisNameID
+    fvar.put_slice(b"wght");
+    for value in [100, 400, 900] {
+        fvar.put_u32(value << 16);
+    }
+    fvar.put_u16(0);
+    fvar.put_u16(256);
+    // instance: subfamilyNameID, flags, coordinates
+    fvar.put_u16(257);
+    fvar.put_u16(0);
+    fvar.put_u32(900 << 16);
+
+    let mut glyf = Vec::new();
+    // numberOfContours, bounding box, endPtsOfContours, instructionLength
+    glyf.put_i16(1);
+    for value in [0, 0, 100, 100] {
+        glyf.put_i16(value);
+    }
+    glyf.put_u16(2);
+    glyf.put_u16(0);
+    // three on-curve points with word coordinates, then x and y deltas
+    glyf.put_slice(&[1, 1, 1]);
+    for delta in [0, 100, -50, 0, 0, 100] {
+        glyf.put_i16(delta);
+    }
+    // padding to an even length for short offsets
+    glyf.put_u8(0);
+
+    let mut gvar = Vec::new();
+    // version, axisCount, sharedTupleCount, sharedTuplesOffset, glyphCount, flags,
+    // glyphVariationDataArrayOffset, then the (short) offsets of the glyph's data
+    gvar.put_u32(0x0001_0000);
+    gvar.put_u16(1);
+    gvar.put_u16(0);
+    gvar.put_u32(20);
+    gvar.put_u16(1);
+    gvar.put_u16(0);
+    gvar.put_u32(24);
+    gvar.put_u16(0);
+    gvar.put_u16(9);
+    // tupleVariationCount, dataOffset, then a tuple with an embedded peak and private points:
+    // variationDataSize, tupleIndex, peak of 1.0
+    gvar.put_u16(1);
+    gvar.put_u16(10);
+    gvar.put_u16(7);
+    gvar.put_u16(0x8000 | 0x2000);
+    gvar.put_u16(0x4000);
+    // all points, x deltas of 50 for the outline and 0 for the four phantom points, no y deltas,
+    // then padding
+    gvar.put_slice(&[0, 0x02, 50, 50, 50, 0x83, 0x86, 0]);
+
+    let mut hhea = vec![0; 36];
+    hhea[..4].copy_from_slice(&[0, 1, 0, 0]);
+    // numberOfHMetrics
+    hhea[34..].copy_from_slice(&1u16.to_be_bytes());
+
+    vec![
+        (b"fvar", fvar),
+        (b"glyf", glyf),
+        (b"loca", vec![0, 0, 0, 15]),
+        (b"gvar", gvar),
+        (b"head", head_table()),
+        (b"hhea", hhea),
+        // advanceWidth, lsb
+        (b"hmtx", vec![0x01, 0xF4, 0, 0]),
+        // version 0.5, numGlyphs
+        (b"maxp", vec![0, 0, 0x50, 0, 0, 1]),
+    ]
+}
@@ -3,6 +3,8 @@
 use bytes::BufMut;
 use four_cc::FourCC;
 
+use crate::buffer_util::{BufExt, SafeBuf, TruncatedError};
+
 /// Calculates the size of the OpenType table directory
 pub fn calculate_header_size(num_tables: usize) -> usize {
     // sfnt_version:   4 bytes
@@ -26,6 +28,8 @@ pub struct TableDirectory {
 
 impl TableDirectory {
     /// Build a new table directory, sorting the table records.
+    ///
+    /// OpenType requires the records to be sorted by tag, so the original order is not kept.
     pub fn new(sfnt_version: FourCC, mut table_records: Vec<TableRecord>) -> Self {
         table_records.sort_unstable_by_key(|table| table.tag.0);
         let num_tables: u16 = table_records
@@ -69,7 +73,7 @@ impl TableDirectory {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct TableRecord {
     pub tag: FourCC,
     pub checksum: u32,
@@ -78,6 +82,15 @@ pub struct TableRecord {
 }
 
 impl TableRecord {
+    pub fn from_buf(buffer: &mut impl SafeBuf) -> Result<Self, TruncatedError> {
+        Ok(TableRecord {
+            tag: buffer.try_get_four_cc()?,
+            checksum: buffer.try_get_u32()?,
+            offset: buffer.try_get_u32()?,
+            length: buffer.try_get_u32()?,
+        })
+    }
+
     pub fn write_to_buf(&self, buffer: &mut impl BufMut) {
         buffer.put_slice(&self.tag.0);
         buffer.put_u32(self.checksum);
@@ -89,3 +102,53 @@ impl TableRecord {
         self.offset as usize..self.offset as usize + self.length as usize
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use four_cc::FourCC;
+
+    use super::{calculate_header_size, TableDirectory, TableRecord};
+
+    /// Builds a table directory with `num_tables` empty tables and returns the search fields it
+    /// writes.
+    fn search_fields(num_tables: u16) -> [u16; 3] {
+        let table_records = (0..num_tables)
+            .map(|index| TableRecord {
+                tag: FourCC((index as u32).to_be_bytes()),
+                checksum: 0,
+                offset: 0,
+                length: 0,
+            })
+            .collect();
+        let mut header = Vec::new();
+        TableDirectory::new(FourCC(*b"true"), table_records).write_to_buf(&mut header);
+        assert_eq!(header.len(), calculate_header_size(num_tables as usize));
+        assert_eq!(header[4..6], num_tables.to_be_bytes());
+        [6, 8, 10].map(|offset| u16::from_be_bytes([header[offset], header[offset + 1]]))
+    }
+
+    #[test]
+    fn binary_search_fields() {
+        for num_tables in (1..=40u16).chain([63, 64, 65, 127, 128, 129, 4095]) {
+            // the largest power of two that's at most `num_tables`
+            let (mut max_power_of_two, mut entry_selector) = (1, 0);
+            while max_power_of_two * 2 <= num_tables {
+                max_power_of_two *= 2;
+                entry_selector += 1;
+            }
+            let search_range = max_power_of_two * 16;
+            let range_shift = num_tables * 16 - search_range;
+            assert_eq!(
+                search_fields(num_tables),
+                [search_range, entry_selector, range_shift],
+                "{} tables",
+                num_tables
+            );
+        }
+        // powers of two have nothing left over for `rangeShift`
+        assert_eq!(search_fields(1), [16, 0, 0]);
+        assert_eq!(search_fields(16), [256, 4, 0]);
+        assert_eq!(search_fields(15), [128, 3, 112]);
+        assert_eq!(search_fields(17), [256, 4, 16]);
+    }
+}
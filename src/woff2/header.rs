@@ -18,8 +18,15 @@ pub enum Woff2HeaderError {
     OverlappingStreams,
 }
 
+/// The size of the WOFF2 header, which the table directory directly follows.
+pub const HEADER_SIZE: usize = 48;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Woff2Header {
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::tag::serialize_tag"))]
     pub signature: FourCC,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::tag::serialize_tag"))]
     pub flavor: FourCC,
     pub length: u32,
     pub num_tables: u16,
@@ -37,7 +44,7 @@ pub struct Woff2Header {
 
 impl Woff2Header {
     pub fn from_buf(buffer: &mut impl Buf) -> Result<Self, Woff2HeaderError> {
-        if buffer.remaining() < 48 {
+        if buffer.remaining() < HEADER_SIZE {
             return Err(Woff2HeaderError::Truncated);
         }
 
@@ -59,6 +66,13 @@ impl Woff2Header {
         })
     }
 
+    /// Reads the header from the start of `data`, also returning the offset of the table
+    /// directory in `data`.
+    pub fn from_slice(mut data: &[u8]) -> Result<(Self, usize), Woff2HeaderError> {
+        let header = Self::from_buf(&mut data)?;
+        Ok((header, HEADER_SIZE))
+    }
+
     pub fn is_valid_header(&self) -> Result<(), Woff2HeaderError> {
         if self.signature != WOFF2_SIGNATURE {
             return Err(Woff2HeaderError::InvalidMagicWord);
@@ -70,18 +84,59 @@ impl Woff2Header {
     }
 }
 
+impl TryFrom<&[u8]> for Woff2Header {
+    type Error = Woff2HeaderError;
+
+    /// Reads the header from the start of `data`, which must hold at least [`HEADER_SIZE`] bytes.
+    fn try_from(mut data: &[u8]) -> Result<Self, Self::Error> {
+        Self::from_buf(&mut data)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::Cursor;
 
-    use crate::test_resources::LATO_V22_LATIN_REGULAR;
+    use crate::{
+        test_resources::LATO_V22_LATIN_REGULAR, woff2::table_directory::Woff2TableDirectory,
+    };
 
-    use super::Woff2Header;
+    use super::{Woff2Header, Woff2HeaderError, HEADER_SIZE};
 
     #[test]
     fn test_header() {
         let mut buffer = Cursor::new(LATO_V22_LATIN_REGULAR);
         let header = Woff2Header::from_buf(&mut buffer).unwrap();
         assert!(header.is_valid_header().is_ok());
+        assert_eq!(buffer.position() as usize, HEADER_SIZE);
+        assert_eq!(
+            header,
+            Woff2Header::from_slice(LATO_V22_LATIN_REGULAR).unwrap().0
+        );
+    }
+
+    #[test]
+    fn table_directory_offset() {
+        let (header, offset) = Woff2Header::from_slice(LATO_V22_LATIN_REGULAR).unwrap();
+        assert_eq!(offset, HEADER_SIZE);
+        assert_eq!(header.num_tables, 16);
+        let table_directory =
+            Woff2TableDirectory::from_slice(LATO_V22_LATIN_REGULAR, offset, &header).unwrap();
+        assert_eq!(&table_directory.tables[0].tag.0, b"GPOS");
+
+        assert!(Woff2Header::from_slice(&LATO_V22_LATIN_REGULAR[..HEADER_SIZE - 1]).is_err());
+    }
+
+    #[test]
+    fn try_from_slice() {
+        let header = Woff2Header::try_from(&LATO_V22_LATIN_REGULAR[..HEADER_SIZE]).unwrap();
+        assert_eq!(
+            header,
+            Woff2Header::from_slice(LATO_V22_LATIN_REGULAR).unwrap().0
+        );
+        assert!(matches!(
+            Woff2Header::try_from(&LATO_V22_LATIN_REGULAR[..HEADER_SIZE - 1]),
+            Err(Woff2HeaderError::Truncated)
+        ));
     }
 }
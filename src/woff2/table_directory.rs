@@ -1,5 +1,8 @@
 //! The WOFF2 table directory
 
+#[cfg(feature = "streaming")]
+use std::io::Read;
+
 use bytes::Buf;
 use four_cc::FourCC;
 use thiserror::Error;
@@ -7,33 +10,35 @@ use thiserror::Error;
 use crate::{
     buffer_util::{pad_to_multiple_of_four, Base128Error, BufExt, SafeBuf, TruncatedError},
     checksum::{calculate_checksum, set_checksum_adjustment, ChecksumError},
-    glyf_decoder::{decode_glyf_table, GlyfDecoderError},
+    glyf_decoder::{
+        decode_glyf_table_with_max_points, DecodedGlyf, GlyfDecoderError,
+        DEFAULT_MAX_POINTS_PER_GLYPH,
+    },
+    hmtx_decoder::{decode_hmtx_table, HmtxDecoderError},
+    tag::display_tag,
     ttf_header::TableRecord,
+    woff2::header::Woff2Header,
 };
 
 #[derive(Error, Debug)]
 pub enum TableDirectoryError {
-    #[error("Table Directory truncated")]
-    Truncated,
+    /// The directory entry at `offset` is cut off
+    #[error("Table Directory truncated at offset {offset}")]
+    Truncated { offset: usize },
     #[error("Invalid numeric value")]
     InvalidNumeric,
+    #[error("Total table length overflows")]
+    OffsetOverflow,
+    #[error("Unknown transformation version {1} for `{}` table", display_tag(*.0))]
+    UnknownTransformVersion(FourCC, u8),
+    #[error("Duplicate `{}` table", display_tag(*.0))]
+    DuplicateTag(FourCC),
+    #[error("`{}` table lies outside the decompressed table data", display_tag(*.0))]
+    TableOutOfBounds(FourCC),
 }
 
-impl From<Base128Error> for TableDirectoryError {
-    fn from(e: Base128Error) -> Self {
-        match e {
-            Base128Error::Truncated => TableDirectoryError::Truncated,
-            _ => TableDirectoryError::InvalidNumeric,
-        }
-    }
-}
-
-impl From<TruncatedError> for TableDirectoryError {
-    fn from(TruncatedError: TruncatedError) -> Self {
-        TableDirectoryError::Truncated
-    }
-}
 /// A WOFF2 table directory.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Woff2TableDirectory {
     pub tables: Vec<TableDirectoryEntry>,
     pub uncompressed_length: u32,
@@ -42,12 +47,45 @@ pub struct Woff2TableDirectory {
 impl Woff2TableDirectory {
     /// Read the table directory from the buffer, returning the directory entries and the total length
     /// of the uncompressed data.
+    ///
+    /// Offsets in errors count from where the buffer started.
     pub fn from_buf(buffer: &mut impl Buf, num_tables: u16) -> Result<Self, TableDirectoryError> {
-        let mut tables = Vec::with_capacity(num_tables as usize);
+        Self::from_buf_at(buffer, num_tables, 0)
+    }
+
+    /// Reads the table directory of the WOFF2 file in `data`, starting at `offset` (as returned by
+    /// [`Woff2Header::from_slice`](super::header::Woff2Header::from_slice)).
+    ///
+    /// The directory has to end within the file length declared in `header`.
+    pub fn from_slice(
+        data: &[u8],
+        offset: usize,
+        header: &Woff2Header,
+    ) -> Result<Self, TableDirectoryError> {
+        let end = data.len().min(header.length as usize);
+        let mut directory = data
+            .get(offset..end)
+            .ok_or(TableDirectoryError::Truncated { offset })?;
+        Self::from_buf_at(&mut directory, header.num_tables, offset)
+    }
+
+    /// Like [`Self::from_buf`], but with the buffer starting at `offset` in the file.
+    pub(crate) fn from_buf_at(
+        buffer: &mut impl Buf,
+        num_tables: u16,
+        offset: usize,
+    ) -> Result<Self, TableDirectoryError> {
+        let buffer_length = buffer.remaining();
+        // Each entry takes at least two bytes, so don't let a bogus `num_tables` force a large
+        // allocation for a tiny buffer.
+        const MIN_ENTRY_SIZE: usize = 2;
+        let mut tables =
+            Vec::with_capacity((num_tables as usize).min(buffer.remaining() / MIN_ENTRY_SIZE));
         let mut src_offset: u32 = 0;
 
         for _ in 0..num_tables {
-            let entry = PartialTableDirectoryEntry::from_buf(buffer)?;
+            let entry_offset = offset + buffer_length - buffer.remaining();
+            let entry = PartialTableDirectoryEntry::from_buf(buffer, entry_offset)?;
             let src_length = entry.transform_length.unwrap_or(entry.orig_length);
             let complete_entry = TableDirectoryEntry {
                 transformed: entry.transformed,
@@ -57,7 +95,9 @@ impl Woff2TableDirectory {
                 src_offset,
             };
             tables.push(complete_entry);
-            src_offset += src_length;
+            src_offset = src_offset
+                .checked_add(src_length)
+                .ok_or(TableDirectoryError::OffsetOverflow)?;
         }
         Ok(Woff2TableDirectory {
             tables,
@@ -65,104 +105,486 @@ impl Woff2TableDirectory {
         })
     }
 
+    /// Finds the first table with the given tag, in table directory order.
+    ///
+    /// A collection can have several tables with the same tag, for different fonts.
+    pub fn find(&self, tag: FourCC) -> Option<&TableDirectoryEntry> {
+        self.tables.iter().find(|table| table.tag == tag)
+    }
+
+    /// Checks that no two of the tables at `indices` have the same tag.
+    ///
+    /// The tables of a collection are shared between its fonts, so duplicates are only a problem
+    /// within one font.
+    pub fn check_unique_tags(
+        &self,
+        indices: impl IntoIterator<Item = usize>,
+    ) -> Result<(), TableDirectoryError> {
+        let mut tags: Vec<_> = indices
+            .into_iter()
+            .map(|index| self.tables[index].tag.0)
+            .collect();
+        tags.sort_unstable();
+        match tags.windows(2).find(|pair| pair[0] == pair[1]) {
+            Some(pair) => Err(TableDirectoryError::DuplicateTag(FourCC(pair[0]))),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns `(tag, src_length, dest_length, transformed)` for each table, in directory order.
+    ///
+    /// `src_length` is the size of the table within the decompressed stream (after any transform),
+    /// and `dest_length` is the size of the reconstructed table in the output font.
+    ///
+    /// ```
+    /// use woff2::{decode::parse_table_directory, FourCC};
+    ///
+    /// let buffer = std::fs::read("src/test_resources/lato-v22-latin-regular.woff2").unwrap();
+    /// let directory = parse_table_directory(&mut std::io::Cursor::new(buffer)).unwrap();
+    /// let summary = directory.table_summary();
+    /// assert_eq!(summary.len(), 16);
+    ///
+    /// let (_, src_length, dest_length, transformed) = summary
+    ///     .iter()
+    ///     .find(|(tag, ..)| *tag == FourCC(*b"glyf"))
+    ///     .copied()
+    ///     .unwrap();
+    /// assert!(transformed);
+    /// assert!(src_length < dest_length);
+    /// ```
+    pub fn table_summary(&self) -> Vec<(FourCC, u32, u32, bool)> {
+        self.tables
+            .iter()
+            .map(|table| {
+                (
+                    table.tag,
+                    table.src_length,
+                    table.dest_length,
+                    table.transformed,
+                )
+            })
+            .collect()
+    }
+
     /// Copies tables (and transforms as necessary) into an output buffer, returning the final
     /// table records.
     ///
-    /// Transformed `glyf` and `loca` tables are handled here. Currently, transformed `hmtx` tables are
-    /// not supported.
+    /// Transformed `glyf`, `loca` and `hmtx` tables are handled here. A transformed `hmtx` table
+    /// needs the `xMin` of each glyph and `numberOfHMetrics` from the `hhea` table, which are
+    /// found wherever those tables are in the directory.
     pub fn write_to_buf(
         &self,
         out_buffer: &mut Vec<u8>,
         decompressed_tables: &[u8],
+    ) -> Result<Vec<TableRecord>, WriteTablesError> {
+        let mut ttf_tables = self.write_tables(
+            out_buffer,
+            decompressed_tables,
+            false,
+            DEFAULT_MAX_POINTS_PER_GLYPH,
+        )?;
+        calculate_table_checksums(out_buffer, &mut ttf_tables);
+        Ok(ttf_tables)
+    }
+
+    /// Like [`write_to_buf`](Self::write_to_buf), but leaves the checksums in the table records
+    /// zero.
+    ///
+    /// With `physical_order`, each `loca` table is written where it is in the table directory
+    /// instead of right after its `glyf` table, so all tables come out in directory order. Simple
+    /// glyphs with more than `max_points_per_glyph` points are rejected.
+    pub(crate) fn write_tables(
+        &self,
+        out_buffer: &mut Vec<u8>,
+        decompressed_tables: &[u8],
+        physical_order: bool,
+        max_points_per_glyph: u16,
     ) -> Result<Vec<TableRecord>, WriteTablesError> {
         // header size should always be a multiple of four
         assert_eq!(out_buffer.len() & 3, 0);
         let num_tables = self.tables.len();
+        // the checksums are filled in once all of the tables have been written
         let mut ttf_tables = Vec::with_capacity(num_tables);
-        let mut tables_iter = self.tables.iter();
-        while let Some(&table) = tables_iter.next() {
+        // `loca` tables are written along with the `glyf` table before them
+        let loca_indices = self.pair_glyf_loca()?;
+        // reconstructed `loca` tables, waiting for their turn in the table directory
+        let mut pending_loca = vec![None; num_tables];
+        let mut loca_index_formats = Vec::new();
+        // A transformed `hmtx` table needs the `xMin`s of the glyphs, and its `glyf` table may come
+        // after it in the directory, so the `glyf` tables are decoded in a first pass
+        let mut decoded_glyfs = self
+            .tables
+            .iter()
+            .map(|table| {
+                if table.tag != GLYF_TAG || !table.transformed {
+                    return Ok(None);
+                }
+                let decoded = decode_glyf_table_with_max_points(
+                    &decompressed_tables[table.try_get_source_range(decompressed_tables.len())?],
+                    max_points_per_glyph,
+                )?;
+                debug!(
+                    "Decoded transformed `glyf` ({} bytes) and `loca` ({} bytes)",
+                    decoded.glyf.len(),
+                    decoded.loca.len()
+                );
+                Ok(Some(decoded))
+            })
+            .collect::<Result<Vec<_>, WriteTablesError>>()?;
+        // the last transformed `glyf` table written, whose `xMin`s go with the next `hmtx` table
+        let mut x_mins_index = None;
+        for (index, &table) in self.tables.iter().enumerate() {
             match table.tag {
                 GLYF_TAG => {
-                    let next_table = tables_iter
-                        .clone()
-                        .find(|t| t.tag == LOCA_TAG)
-                        .ok_or(WriteTablesError::MissingLocaTable)?;
-
-                    if next_table.transformed != table.transformed {
-                        return Err(WriteTablesError::GlyfLocaDifferentTransform);
-                    }
-                    if table.transformed {
-                        let (glyf, loca) =
-                            decode_glyf_table(&decompressed_tables[table.get_source_range()])?;
+                    let loca_index = loca_indices[index].unwrap();
+                    let next_table = &self.tables[loca_index];
+                    if let Some(DecodedGlyf {
+                        glyf,
+                        loca,
+                        index_format,
+                        ..
+                    }) = &mut decoded_glyfs[index]
+                    {
+                        x_mins_index = Some(index);
+                        loca_index_formats.push(*index_format);
                         ttf_tables.push(TableRecord {
                             tag: table.tag,
-                            checksum: calculate_checksum(&glyf),
+                            checksum: 0,
                             offset: out_buffer.len() as u32,
                             length: glyf.len() as u32,
                         });
-                        out_buffer.extend_from_slice(&glyf);
-                        pad_to_multiple_of_four(out_buffer);
-                        ttf_tables.push(TableRecord {
-                            tag: next_table.tag,
-                            checksum: calculate_checksum(&loca),
-                            offset: out_buffer.len() as u32,
-                            length: loca.len() as u32,
-                        });
-                        out_buffer.extend_from_slice(&loca);
+                        out_buffer.extend_from_slice(glyf);
                         pad_to_multiple_of_four(out_buffer);
+                        let loca = std::mem::take(loca);
+                        if physical_order {
+                            pending_loca[loca_index] = Some(loca);
+                        } else {
+                            push_loca_table_record(&loca, out_buffer, &mut ttf_tables);
+                        }
                     } else {
                         push_simple_table_record(
                             table,
                             decompressed_tables,
                             out_buffer,
                             &mut ttf_tables,
-                        );
-                        push_simple_table_record(
-                            *next_table,
-                            decompressed_tables,
-                            out_buffer,
-                            &mut ttf_tables,
-                        );
+                        )?;
+                        if !physical_order {
+                            push_simple_table_record(
+                                *next_table,
+                                decompressed_tables,
+                                out_buffer,
+                                &mut ttf_tables,
+                            )?;
+                        }
                     }
                 }
-                // - Spec: https://www.w3.org/TR/WOFF2/#table_order
-                // The loca table MUST follow the glyf table in the table directory. When WOFF2 file contains individually encoded font file, the table directory MAY contain other tables inserted between glyf and loca tables; For example, the following order of tables: 'cmap', 'glyf', 'hhea', 'hmtx', 'loca', 'maxp' ... is acceptable for individually encoded font files;
-                // TODO: however when WOFF2 contains a font collection file each loca table MUST immediately follow its corresponding glyf table.
+                LOCA_TAG if physical_order => match pending_loca[index].take() {
+                    Some(loca) => push_loca_table_record(&loca, out_buffer, &mut ttf_tables),
+                    None => push_simple_table_record(
+                        table,
+                        decompressed_tables,
+                        out_buffer,
+                        &mut ttf_tables,
+                    )?,
+                },
                 LOCA_TAG => {}
                 HEAD_TAG => {
                     let offset = out_buffer.len();
-                    let src = &decompressed_tables[table.get_source_range()];
+                    let src = &decompressed_tables
+                        [table.try_get_source_range(decompressed_tables.len())?];
                     out_buffer.extend_from_slice(src);
                     let head_table = &mut out_buffer[offset..];
                     set_checksum_adjustment(head_table, 0)?;
                     ttf_tables.push(TableRecord {
                         tag: table.tag,
-                        checksum: calculate_checksum(head_table),
+                        checksum: 0,
                         offset: offset as u32,
                         length: head_table.len() as u32,
                     });
                     pad_to_multiple_of_four(out_buffer);
                 }
                 HMTX_TAG if table.transformed => {
-                    return Err(WriteTablesError::Unsupported("transformed hmtx table"));
+                    // an `hmtx` table before any `glyf` table goes with the first one
+                    let glyf_index = x_mins_index
+                        .or_else(|| decoded_glyfs.iter().position(Option::is_some))
+                        .ok_or(WriteTablesError::HmtxWithoutTransformedGlyf)?;
+                    let x_mins = &decoded_glyfs[glyf_index].as_ref().unwrap().x_mins;
+                    let hmtx = decode_hmtx_table(
+                        &decompressed_tables
+                            [table.try_get_source_range(decompressed_tables.len())?],
+                        self.number_of_h_metrics(decompressed_tables)?,
+                        x_mins,
+                    )?;
+                    debug!("Decoded transformed `hmtx` ({} bytes)", hmtx.len());
+                    ttf_tables.push(TableRecord {
+                        tag: table.tag,
+                        checksum: 0,
+                        offset: out_buffer.len() as u32,
+                        length: hmtx.len() as u32,
+                    });
+                    out_buffer.extend_from_slice(&hmtx);
+                    pad_to_multiple_of_four(out_buffer);
                 }
                 _ => push_simple_table_record(
                     table,
                     decompressed_tables,
                     out_buffer,
                     &mut ttf_tables,
-                ),
+                )?,
             }
         }
         assert_eq!(ttf_tables.len(), num_tables);
+        self.match_head_index_format(out_buffer, &ttf_tables, &loca_index_formats)?;
+        Ok(ttf_tables)
+    }
+
+    /// Like [`write_to_buf`](Self::write_to_buf), but reads the table data from `reader` as it's
+    /// needed instead of from a buffer holding all of it.
+    ///
+    /// Untransformed tables are read straight into `out_buffer`, so only transformed tables are
+    /// held in memory on their own. Tables are written in the order they're read, so a `loca`
+    /// table that doesn't directly follow its `glyf` table ends up somewhere else than where
+    /// `write_to_buf` puts it. A transformed `hmtx` table has to come after the `glyf` and `hhea`
+    /// tables.
+    #[cfg(feature = "streaming")]
+    pub fn write_to_buf_streaming(
+        &self,
+        out_buffer: &mut Vec<u8>,
+        reader: &mut dyn Read,
+    ) -> Result<Vec<TableRecord>, WriteTablesError> {
+        self.write_tables_streaming(out_buffer, reader, DEFAULT_MAX_POINTS_PER_GLYPH)
+    }
+
+    /// Like [`write_to_buf_streaming`](Self::write_to_buf_streaming), but rejects simple glyphs
+    /// with more than `max_points_per_glyph` points.
+    #[cfg(feature = "streaming")]
+    pub(crate) fn write_tables_streaming(
+        &self,
+        out_buffer: &mut Vec<u8>,
+        reader: &mut dyn Read,
+        max_points_per_glyph: u16,
+    ) -> Result<Vec<TableRecord>, WriteTablesError> {
+        // header size should always be a multiple of four
+        assert_eq!(out_buffer.len() & 3, 0);
+        let num_tables = self.tables.len();
+        let mut ttf_tables = Vec::with_capacity(num_tables);
+        let loca_indices = self.pair_glyf_loca()?;
+        // reconstructed `loca` tables, waiting for their turn in the table directory
+        let mut pending_loca = vec![None; num_tables];
+        let mut loca_index_formats = Vec::new();
+        let mut x_mins = None;
+        let mut number_of_h_metrics = None;
+        for (index, &table) in self.tables.iter().enumerate() {
+            let offset = out_buffer.len();
+            match table.tag {
+                GLYF_TAG if table.transformed => {
+                    let DecodedGlyf {
+                        glyf,
+                        loca,
+                        index_format,
+                        x_mins: glyph_x_mins,
+                    } = decode_glyf_table_with_max_points(
+                        &read_table(reader, table)?,
+                        max_points_per_glyph,
+                    )?;
+                    debug!(
+                        "Decoded transformed `glyf` ({} bytes) and `loca` ({} bytes)",
+                        glyf.len(),
+                        loca.len()
+                    );
+                    x_mins = Some(glyph_x_mins);
+                    loca_index_formats.push(index_format);
+                    out_buffer.extend_from_slice(&glyf);
+                    pending_loca[loca_indices[index].unwrap()] = Some(loca);
+                }
+                LOCA_TAG if table.transformed => {
+                    // the transformed table has no data of its own
+                    read_table(reader, table)?;
+                    out_buffer.extend_from_slice(&pending_loca[index].take().unwrap());
+                }
+                HMTX_TAG if table.transformed => {
+                    let x_mins = match x_mins.as_deref() {
+                        Some(x_mins) => x_mins,
+                        None if self
+                            .tables
+                            .iter()
+                            .any(|table| table.tag == GLYF_TAG && table.transformed) =>
+                        {
+                            return Err(WriteTablesError::Unsupported(
+                                "transformed `hmtx` table before the `glyf` table when streaming",
+                            ))
+                        }
+                        None => return Err(WriteTablesError::HmtxWithoutTransformedGlyf),
+                    };
+                    let number_of_h_metrics = match number_of_h_metrics {
+                        Some(number_of_h_metrics) => number_of_h_metrics,
+                        None if self.find(HHEA_TAG).is_some() => {
+                            return Err(WriteTablesError::Unsupported(
+                                "transformed `hmtx` table before the `hhea` table when streaming",
+                            ))
+                        }
+                        None => return Err(WriteTablesError::InvalidHheaTable),
+                    };
+                    let hmtx = decode_hmtx_table(
+                        &read_table(reader, table)?,
+                        number_of_h_metrics,
+                        x_mins,
+                    )?;
+                    out_buffer.extend_from_slice(&hmtx);
+                }
+                _ => {
+                    read_table_into(reader, table, out_buffer)?;
+                    let src = &mut out_buffer[offset..];
+                    match table.tag {
+                        HEAD_TAG => set_checksum_adjustment(src, 0)?,
+                        HHEA_TAG => {
+                            let hhea_number_of_h_metrics =
+                                src.get(34..36).ok_or(WriteTablesError::InvalidHheaTable)?;
+                            number_of_h_metrics = Some(u16::from_be_bytes(
+                                hhea_number_of_h_metrics.try_into().unwrap(),
+                            ));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            ttf_tables.push(TableRecord {
+                tag: table.tag,
+                checksum: calculate_checksum(&out_buffer[offset..]),
+                offset: offset as u32,
+                length: (out_buffer.len() - offset) as u32,
+            });
+            pad_to_multiple_of_four(out_buffer);
+        }
+        for index in self.match_head_index_format(out_buffer, &ttf_tables, &loca_index_formats)? {
+            let head = &mut ttf_tables[index];
+            head.checksum = calculate_checksum(&out_buffer[head.get_range()]);
+        }
         Ok(ttf_tables)
     }
+
+    /// Sets `indexToLocFormat` in the written `head` tables to the index format of the
+    /// reconstructed `loca` tables, which differs from the original format if the `loca` table was
+    /// promoted to long offsets. Returns the indices of the `head` table records that were changed.
+    ///
+    /// With several `glyf` tables it's unknown which `head` tables go with which `loca` table, so
+    /// a `head` table that would need changing is an error.
+    fn match_head_index_format(
+        &self,
+        out_buffer: &mut [u8],
+        ttf_tables: &[TableRecord],
+        loca_index_formats: &[u16],
+    ) -> Result<Vec<usize>, WriteTablesError> {
+        let Some(&index_format) = loca_index_formats.first() else {
+            return Ok(Vec::new());
+        };
+        let num_glyf_tables = self
+            .tables
+            .iter()
+            .filter(|table| table.tag == GLYF_TAG)
+            .count();
+        let mut changed = Vec::new();
+        for (index, record) in ttf_tables.iter().enumerate() {
+            if record.tag != HEAD_TAG {
+                continue;
+            }
+            let Some(head_index_format) = out_buffer[record.get_range()].get_mut(50..52) else {
+                continue;
+            };
+            if head_index_format == index_format.to_be_bytes() {
+                continue;
+            }
+            if num_glyf_tables > 1 {
+                return Err(WriteTablesError::Unsupported(
+                    "`loca` index format differs from the `head` table in a collection",
+                ));
+            }
+            debug!("Setting `indexToLocFormat` to {}", index_format);
+            head_index_format.copy_from_slice(&index_format.to_be_bytes());
+            changed.push(index);
+        }
+        Ok(changed)
+    }
+
+    /// Finds the `loca` table of each `glyf` table, which is the first one after it that doesn't
+    /// belong to an earlier `glyf` table. The result has the index of the `loca` table at the
+    /// index of each `glyf` table.
+    fn pair_glyf_loca(&self) -> Result<Vec<Option<usize>>, WriteTablesError> {
+        let num_tables = self.tables.len();
+        let mut loca_indices = vec![None; num_tables];
+        let mut paired_loca = vec![false; num_tables];
+        for (index, table) in self.tables.iter().enumerate() {
+            match table.tag {
+                GLYF_TAG => {
+                    let loca_index = (index + 1..num_tables)
+                        .find(|&i| self.tables[i].tag == LOCA_TAG && !paired_loca[i])
+                        .ok_or(WriteTablesError::MissingLocaTable)?;
+                    if self.tables[loca_index].transformed != table.transformed {
+                        return Err(WriteTablesError::GlyfLocaDifferentTransform {
+                            glyf_transformed: table.transformed,
+                            loca_transformed: self.tables[loca_index].transformed,
+                        });
+                    }
+                    paired_loca[loca_index] = true;
+                    loca_indices[index] = Some(loca_index);
+                }
+                // - Spec: https://www.w3.org/TR/WOFF2/#table_order
+                // The loca table MUST follow the glyf table in the table directory. When WOFF2 file contains individually encoded font file, the table directory MAY contain other tables inserted between glyf and loca tables; For example, the following order of tables: 'cmap', 'glyf', 'hhea', 'hmtx', 'loca', 'maxp' ... is acceptable for individually encoded font files;
+                // TODO: however when WOFF2 contains a font collection file each loca table MUST immediately follow its corresponding glyf table.
+                LOCA_TAG if !paired_loca[index] => {
+                    return Err(
+                        if self.tables[index + 1..]
+                            .iter()
+                            .any(|table| table.tag == GLYF_TAG)
+                        {
+                            WriteTablesError::LocaBeforeGlyf
+                        } else {
+                            WriteTablesError::MissingGlyfTable
+                        },
+                    );
+                }
+                _ => {}
+            }
+        }
+        Ok(loca_indices)
+    }
+
+    /// Reads `numberOfHMetrics` from the `hhea` table.
+    fn number_of_h_metrics(&self, decompressed_tables: &[u8]) -> Result<u16, WriteTablesError> {
+        let hhea = self
+            .find(HHEA_TAG)
+            .ok_or(WriteTablesError::InvalidHheaTable)?;
+        let number_of_h_metrics = decompressed_tables
+            [hhea.try_get_source_range(decompressed_tables.len())?]
+        .get(34..36)
+        .ok_or(WriteTablesError::InvalidHheaTable)?;
+        Ok(u16::from_be_bytes(number_of_h_metrics.try_into().unwrap()))
+    }
+}
+
+/// Lists the table tags in directory order, separated by spaces, with a `*` after each transformed
+/// table, e.g. `cmap glyf* loca* head`. Trailing spaces in tags (as in `cvt `) are left out.
+impl std::fmt::Display for Woff2TableDirectory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, table) in self.tables.iter().enumerate() {
+            if index > 0 {
+                f.write_str(" ")?;
+            }
+            f.write_str(display_tag(table.tag).trim_end())?;
+            if table.transformed {
+                f.write_str("*")?;
+            }
+        }
+        Ok(())
+    }
 }
 
 /// A WOFF2 table directory entry.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct TableDirectoryEntry {
     pub transformed: bool,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::tag::serialize_tag"))]
     pub tag: FourCC,
     /// The original length of the table (before any transformations)
     pub dest_length: u32,
@@ -177,6 +599,20 @@ impl TableDirectoryEntry {
     pub fn get_source_range(&self) -> std::ops::Range<usize> {
         self.src_offset as usize..self.src_offset as usize + self.src_length as usize
     }
+
+    /// Like [`get_source_range`](Self::get_source_range), but checks that the range fits in
+    /// decompressed table data of `stream_len` bytes, without overflowing on 32-bit targets.
+    pub fn try_get_source_range(
+        &self,
+        stream_len: usize,
+    ) -> Result<std::ops::Range<usize>, TableDirectoryError> {
+        let start = self.src_offset as usize;
+        start
+            .checked_add(self.src_length as usize)
+            .filter(|&end| end <= stream_len)
+            .map(|end| start..end)
+            .ok_or(TableDirectoryError::TableOutOfBounds(self.tag))
+    }
 }
 
 struct PartialTableDirectoryEntry {
@@ -187,26 +623,43 @@ struct PartialTableDirectoryEntry {
 }
 
 impl PartialTableDirectoryEntry {
-    fn from_buf(buffer: &mut impl Buf) -> Result<Self, TableDirectoryError> {
-        let flags = buffer.try_get_u8()?;
+    /// Reads the entry at `offset` in the file.
+    fn from_buf(buffer: &mut impl Buf, offset: usize) -> Result<Self, TableDirectoryError> {
+        let truncated = |TruncatedError| TableDirectoryError::Truncated { offset };
+        let base_128 = |error| match error {
+            Base128Error::Truncated => TableDirectoryError::Truncated { offset },
+            _ => TableDirectoryError::InvalidNumeric,
+        };
+        let flags = buffer.try_get_u8().map_err(truncated)?;
         let preprocessing_transformation_version = flags & 0xC0;
         let table_ref = flags & 0x3f;
         let tag = if table_ref == 0x3f {
-            buffer.try_get_four_cc()?
+            buffer.try_get_four_cc().map_err(truncated)?
         } else {
             KNOWN_TABLE_TAGS[table_ref as usize]
         };
 
-        let orig_length = buffer.try_get_base_128()?;
-        let is_null_transform = if tag == FourCC(*b"glyf") || tag == FourCC(*b"loca") {
-            preprocessing_transformation_version == 0xC0
+        let orig_length = buffer.try_get_base_128().map_err(base_128)?;
+        let is_null_transform = if tag == GLYF_TAG || tag == LOCA_TAG {
+            // for glyf and loca, version 0 is the glyf transform and version 3 is the null
+            // transform; versions 1 and 2 aren't defined
+            match preprocessing_transformation_version {
+                0x00 => false,
+                0xC0 => true,
+                _ => {
+                    return Err(TableDirectoryError::UnknownTransformVersion(
+                        tag,
+                        preprocessing_transformation_version >> 6,
+                    ))
+                }
+            }
         } else {
             preprocessing_transformation_version == 0x00
         };
         let transform_length = if is_null_transform {
             None
         } else {
-            Some(buffer.try_get_base_128()?)
+            Some(buffer.try_get_base_128().map_err(base_128)?)
         };
 
         Ok(PartialTableDirectoryEntry {
@@ -218,7 +671,7 @@ impl PartialTableDirectoryEntry {
     }
 }
 
-const KNOWN_TABLE_TAGS: [FourCC; 63] = [
+pub(crate) const KNOWN_TABLE_TAGS: [FourCC; 63] = [
     FourCC(*b"cmap"),
     FourCC(*b"head"),
     FourCC(*b"hhea"),
@@ -284,52 +737,167 @@ const KNOWN_TABLE_TAGS: [FourCC; 63] = [
     FourCC(*b"Sill"),
 ];
 
+pub const CMAP_TAG: FourCC = FourCC(*b"cmap");
+pub const DSIG_TAG: FourCC = FourCC(*b"DSIG");
 pub const GLYF_TAG: FourCC = FourCC(*b"glyf");
 pub const LOCA_TAG: FourCC = FourCC(*b"loca");
 pub const HEAD_TAG: FourCC = FourCC(*b"head");
 pub const HMTX_TAG: FourCC = FourCC(*b"hmtx");
+pub const HHEA_TAG: FourCC = FourCC(*b"hhea");
+pub const MAXP_TAG: FourCC = FourCC(*b"maxp");
 
 #[derive(Debug, Error)]
 pub enum WriteTablesError {
-    #[error("missing loca table in the font")]
+    /// No `loca` table comes after a `glyf` table in the table directory, e.g. because `glyf` is
+    /// the last table. A `loca` table before it is reported as [`Self::LocaBeforeGlyf`] instead.
+    #[error("no loca table follows the glyf table in the table directory")]
     MissingLocaTable,
 
-    #[error("glyf table and loca table have different transformations")]
-    GlyfLocaDifferentTransform,
+    #[error("missing glyf table for the loca table in the font")]
+    MissingGlyfTable,
+
+    #[error("loca table encountered before its glyf table")]
+    LocaBeforeGlyf,
+
+    #[error(
+        "glyf table is {} but loca table is {}; both must use the same transformation",
+        transform_state(*.glyf_transformed),
+        transform_state(*.loca_transformed)
+    )]
+    GlyfLocaDifferentTransform {
+        glyf_transformed: bool,
+        loca_transformed: bool,
+    },
 
     #[error("Truncated `head` table")]
     TruncatedHeadTable,
 
+    #[error("Invalid magic number in `head` table")]
+    BadHeadMagic,
+
+    #[error("Missing or truncated `hhea` table")]
+    InvalidHheaTable,
+
+    #[error("transformed hmtx table without a preceding transformed glyf table")]
+    HmtxWithoutTransformedGlyf,
+
     #[error("Unsupported feature: {0}")]
     Unsupported(&'static str),
 
-    #[error(transparent)]
+    #[error("Invalid transformed glyf table")]
     GlyfDecoderError(#[from] GlyfDecoderError),
+
+    #[error("Invalid transformed hmtx table")]
+    HmtxDecoderError(#[from] HmtxDecoderError),
+
+    #[error("Invalid table directory")]
+    TableDirectory(#[from] TableDirectoryError),
+
+    #[cfg(feature = "streaming")]
+    #[error("Failed to read the decompressed table data")]
+    Decompression(#[source] std::io::Error),
+}
+
+/// Describes whether a table was transformed, for error messages.
+fn transform_state(transformed: bool) -> &'static str {
+    if transformed {
+        "transformed"
+    } else {
+        "null-transformed"
+    }
 }
 
 impl From<ChecksumError> for WriteTablesError {
     fn from(e: ChecksumError) -> WriteTablesError {
         match e {
             ChecksumError::Truncated => WriteTablesError::TruncatedHeadTable,
+            ChecksumError::BadHeadMagic => WriteTablesError::BadHeadMagic,
         }
     }
 }
 
+/// Fills in the checksums of `ttf_tables`, whose data has already been written to `font`.
+///
+/// With the `rayon` feature, the tables are checksummed in parallel.
+pub(crate) fn calculate_table_checksums(font: &[u8], ttf_tables: &mut [TableRecord]) {
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        ttf_tables.par_iter_mut().for_each(|table| {
+            table.checksum = calculate_checksum(&font[table.get_range()]);
+        });
+    }
+    #[cfg(not(feature = "rayon"))]
+    for table in ttf_tables {
+        table.checksum = calculate_checksum(&font[table.get_range()]);
+    }
+}
+
+/// Copies an untransformed table to `out_buffer`, leaving its checksum for later.
 fn push_simple_table_record(
     table: TableDirectoryEntry,
     decompressed_tables: &[u8],
     out_buffer: &mut Vec<u8>,
     ttf_tables: &mut Vec<TableRecord>,
-) {
-    let src = &decompressed_tables[table.get_source_range()];
+) -> Result<(), TableDirectoryError> {
+    let src = &decompressed_tables[table.try_get_source_range(decompressed_tables.len())?];
     ttf_tables.push(TableRecord {
         tag: table.tag,
-        checksum: calculate_checksum(src),
+        checksum: 0,
         offset: out_buffer.len() as u32,
         length: src.len() as u32,
     });
     out_buffer.extend_from_slice(src);
     pad_to_multiple_of_four(out_buffer);
+    Ok(())
+}
+
+/// Adds a reconstructed `loca` table to the output buffer, like [`push_simple_table_record`].
+fn push_loca_table_record(
+    loca: &[u8],
+    out_buffer: &mut Vec<u8>,
+    ttf_tables: &mut Vec<TableRecord>,
+) {
+    ttf_tables.push(TableRecord {
+        tag: LOCA_TAG,
+        checksum: 0,
+        offset: out_buffer.len() as u32,
+        length: loca.len() as u32,
+    });
+    out_buffer.extend_from_slice(loca);
+    pad_to_multiple_of_four(out_buffer);
+}
+
+/// Reads the data of `table` from `reader`.
+#[cfg(feature = "streaming")]
+fn read_table(
+    reader: &mut dyn Read,
+    table: TableDirectoryEntry,
+) -> Result<Vec<u8>, WriteTablesError> {
+    let mut data = Vec::with_capacity(table.src_length as usize);
+    read_table_into(reader, table, &mut data)?;
+    Ok(data)
+}
+
+/// Reads the data of `table` from `reader`, appending it to `out_buffer`.
+#[cfg(feature = "streaming")]
+fn read_table_into(
+    reader: &mut dyn Read,
+    table: TableDirectoryEntry,
+    out_buffer: &mut Vec<u8>,
+) -> Result<(), WriteTablesError> {
+    let length = table.src_length as u64;
+    let read = reader
+        .take(length)
+        .read_to_end(out_buffer)
+        .map_err(WriteTablesError::Decompression)?;
+    if read as u64 != length {
+        return Err(WriteTablesError::Decompression(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "decompressed size mismatch",
+        )));
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -338,8 +906,18 @@ mod tests {
 
     use four_cc::FourCC;
 
-    use super::Woff2TableDirectory;
-    use crate::{test_resources::LATO_V22_LATIN_REGULAR, woff2::header::Woff2Header};
+    use super::{
+        TableDirectoryEntry, TableDirectoryError, Woff2TableDirectory, WriteTablesError, GLYF_TAG,
+        HEAD_TAG, LOCA_TAG,
+    };
+    use crate::{
+        buffer_util::BufMutExt,
+        checksum::calculate_checksum,
+        decode::{decompress_woff2, DecodeOptions},
+        glyf_decoder::tests::glyphs_with_instructions,
+        test_resources::{FONTAWESOME_REGULAR_400, LATO_V22_LATIN_REGULAR, TEST_KERN_ONE_CFF},
+        woff2::header::Woff2Header,
+    };
 
     #[test]
     fn test_sample_font() {
@@ -362,6 +940,303 @@ mod tests {
                 .iter()
                 .map(|table| table.tag)
                 .collect::<Vec<_>>()
-        )
+        );
+        assert_eq!(
+            tables.tables[7],
+            TableDirectoryEntry {
+                transformed: true,
+                tag: GLYF_TAG,
+                dest_length: 50696,
+                src_length: 47197,
+                src_offset: 6957,
+            }
+        );
+        assert_eq!(tables.find(GLYF_TAG), Some(&tables.tables[7]));
+        assert_eq!(
+            tables.to_string(),
+            "GPOS GSUB OS/2 cmap cvt fpgm gasp glyf* loca* head hhea hmtx maxp name post prep"
+        );
+        assert_eq!(tables.find(FourCC(*b"DSIG")), None);
+    }
+
+    #[test]
+    fn table_checksums() {
+        // with the `rayon` feature this compares the parallel checksums against serial ones
+        for font in [
+            LATO_V22_LATIN_REGULAR,
+            FONTAWESOME_REGULAR_400,
+            TEST_KERN_ONE_CFF,
+        ] {
+            let decompressed =
+                decompress_woff2(&mut Cursor::new(font), &DecodeOptions::default()).unwrap();
+            let mut out_buffer = Vec::new();
+            let ttf_tables = decompressed
+                .table_directory
+                .write_to_buf(&mut out_buffer, &decompressed.decompressed_tables)
+                .unwrap();
+            for table in ttf_tables {
+                assert_eq!(
+                    table.checksum,
+                    calculate_checksum(&out_buffer[table.get_range()])
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_glyf_transform_version() {
+        for version in [1, 2] {
+            let mut directory = Vec::new();
+            // glyf
+            directory.push((version << 6) | 10);
+            directory.put_base_128(1000);
+            directory.put_base_128(500);
+            let result = Woff2TableDirectory::from_buf(&mut Cursor::new(directory), 1);
+            assert!(matches!(
+                &result,
+                Err(TableDirectoryError::UnknownTransformVersion(FourCC(tag), v))
+                    if tag == b"glyf" && *v == version
+            ));
+            assert_eq!(
+                result.err().unwrap().to_string(),
+                format!("Unknown transformation version {version} for `glyf` table")
+            );
+        }
+    }
+
+    #[test]
+    fn glyf_transform_versions() {
+        let mut directory = Vec::new();
+        // transformed glyf and loca
+        directory.push(10);
+        directory.put_base_128(1000);
+        directory.put_base_128(500);
+        directory.push(11);
+        directory.put_base_128(100);
+        directory.put_base_128(0);
+        // null-transformed glyf and loca
+        directory.push(0xC0 | 10);
+        directory.put_base_128(1000);
+        directory.push(0xC0 | 11);
+        directory.put_base_128(100);
+        let tables = Woff2TableDirectory::from_buf(&mut Cursor::new(directory), 4).unwrap();
+        let transformed: Vec<_> = tables.tables.iter().map(|t| t.transformed).collect();
+        assert_eq!(transformed, [true, true, false, false]);
+        assert_eq!(tables.uncompressed_length, 500 + 1000 + 100);
+    }
+
+    #[test]
+    fn num_tables_larger_than_directory() {
+        // a few valid entries, then the buffer runs out
+        let mut directory = Vec::new();
+        for _ in 0..3 {
+            directory.push(0);
+            directory.put_base_128(100);
+        }
+        directory.extend_from_slice(&[0x3F, b'a', b'b', b'c']);
+        assert_eq!(directory.len(), 10);
+        let result = Woff2TableDirectory::from_buf(&mut Cursor::new(directory), u16::MAX);
+        assert!(matches!(
+            result,
+            Err(TableDirectoryError::Truncated { offset: 6 })
+        ));
+    }
+
+    #[test]
+    fn total_length_overflow() {
+        let mut directory = Vec::new();
+        for _ in 0..2 {
+            directory.push(0);
+            directory.put_base_128(0x8000_0000);
+        }
+        let result = Woff2TableDirectory::from_buf(&mut Cursor::new(directory), 2);
+        assert!(matches!(result, Err(TableDirectoryError::OffsetOverflow)));
+    }
+
+    /// Writes a directory of null-transformed tables, each 4 bytes long, identified by their index
+    /// in the known tags (10 is `glyf`, 11 is `loca`, 0 is `cmap`).
+    fn write_null_transformed(tags: &[u8]) -> Result<(), WriteTablesError> {
+        let mut directory = Vec::new();
+        for &tag in tags {
+            directory.push(if tag == 10 || tag == 11 {
+                0xC0 | tag
+            } else {
+                tag
+            });
+            directory.put_base_128(4);
+        }
+        let tables =
+            Woff2TableDirectory::from_buf(&mut Cursor::new(directory), tags.len() as u16).unwrap();
+        let decompressed_tables = vec![0; 4 * tags.len()];
+        tables.write_to_buf(&mut Vec::new(), &decompressed_tables)?;
+        Ok(())
+    }
+
+    #[test]
+    fn glyf_loca_order() {
+        write_null_transformed(&[10, 0, 11]).unwrap();
+        assert!(matches!(
+            write_null_transformed(&[11, 0, 10]),
+            Err(WriteTablesError::LocaBeforeGlyf)
+        ));
+        assert!(matches!(
+            write_null_transformed(&[0, 11]),
+            Err(WriteTablesError::MissingGlyfTable)
+        ));
+        assert!(matches!(
+            write_null_transformed(&[10, 0]),
+            Err(WriteTablesError::MissingLocaTable)
+        ));
+        // `glyf` is the last table
+        assert!(matches!(
+            write_null_transformed(&[0, 10]),
+            Err(WriteTablesError::MissingLocaTable)
+        ));
+        // `loca` doesn't have to directly follow `glyf`
+        write_null_transformed(&[10, 0, 2, 11]).unwrap();
+        assert_eq!(
+            WriteTablesError::LocaBeforeGlyf.to_string(),
+            "loca table encountered before its glyf table"
+        );
+        assert_eq!(
+            WriteTablesError::MissingLocaTable.to_string(),
+            "no loca table follows the glyf table in the table directory"
+        );
+    }
+
+    #[test]
+    fn duplicate_tags() {
+        // `cmap`, `glyf`, `cmap`, each four bytes long
+        let directory = [0x00, 4, 0xCA, 4, 0x00, 4];
+        let tables = Woff2TableDirectory::from_buf(&mut Cursor::new(directory), 3).unwrap();
+        tables.check_unique_tags([0, 1]).unwrap();
+        tables.check_unique_tags([1, 2]).unwrap();
+        assert!(matches!(
+            tables.check_unique_tags(0..3),
+            Err(TableDirectoryError::DuplicateTag(tag)) if &tag.0 == b"cmap"
+        ));
+        assert_eq!(
+            TableDirectoryError::DuplicateTag(FourCC(*b"cmap")).to_string(),
+            "Duplicate `cmap` table"
+        );
+    }
+
+    #[test]
+    fn glyf_loca_different_transforms() {
+        // (glyf flags, loca flags); version 0 is the transform and version 3 the null transform
+        for (glyf_flags, loca_flags) in [(0xCA, 0x0B), (0x0A, 0xCB)] {
+            let mut directory = Vec::new();
+            for flags in [glyf_flags, loca_flags] {
+                directory.push(flags);
+                directory.put_base_128(4);
+                if flags & 0xC0 == 0 {
+                    // transformLength
+                    directory.put_base_128(4);
+                }
+            }
+            let tables = Woff2TableDirectory::from_buf(&mut Cursor::new(directory), 2).unwrap();
+            let glyf_transformed = glyf_flags & 0xC0 == 0;
+            let error = tables.write_to_buf(&mut Vec::new(), &[0; 8]).unwrap_err();
+            assert!(matches!(
+                error,
+                WriteTablesError::GlyfLocaDifferentTransform {
+                    glyf_transformed: g,
+                    loca_transformed: l,
+                } if g == glyf_transformed && l != glyf_transformed
+            ));
+        }
+        assert_eq!(
+            WriteTablesError::GlyfLocaDifferentTransform {
+                glyf_transformed: false,
+                loca_transformed: true,
+            }
+            .to_string(),
+            "glyf table is null-transformed but loca table is transformed; both must use the same \
+            transformation"
+        );
+    }
+
+    #[test]
+    fn promoted_loca_sets_head_index_format() {
+        let glyf = glyphs_with_instructions(3, 60000, 0);
+        let mut head = vec![0; 54];
+        head[12..16].copy_from_slice(&[0x5F, 0x0F, 0x3C, 0xF5]);
+        let mut decompressed_tables = glyf.clone();
+        decompressed_tables.extend_from_slice(&head);
+        let entry = |tag, src_offset: usize, src_length: usize| TableDirectoryEntry {
+            transformed: tag != HEAD_TAG,
+            tag,
+            dest_length: 0,
+            src_length: src_length as u32,
+            src_offset: src_offset as u32,
+        };
+        let table_directory = Woff2TableDirectory {
+            tables: vec![
+                entry(GLYF_TAG, 0, glyf.len()),
+                entry(LOCA_TAG, glyf.len(), 0),
+                entry(HEAD_TAG, glyf.len(), head.len()),
+            ],
+            uncompressed_length: decompressed_tables.len() as u32,
+        };
+
+        let mut font = vec![0; 12 + 3 * 16];
+        let records = table_directory
+            .write_to_buf(&mut font, &decompressed_tables)
+            .unwrap();
+        // four long offsets
+        assert_eq!(records[1].length, 16);
+        let head = &font[records[2].get_range()];
+        assert_eq!(head[50..52], [0, 1]);
+        assert_eq!(records[2].checksum, calculate_checksum(head));
+
+        #[cfg(feature = "streaming")]
+        {
+            let mut streamed = vec![0; 12 + 3 * 16];
+            let streamed_records = table_directory
+                .write_to_buf_streaming(&mut streamed, &mut Cursor::new(&decompressed_tables))
+                .unwrap();
+            assert_eq!(streamed, font);
+            assert_eq!(streamed_records, records);
+        }
+    }
+
+    #[test]
+    fn source_range_out_of_bounds() {
+        // the end doesn't fit in 32 bits
+        let table = TableDirectoryEntry {
+            transformed: false,
+            tag: FourCC(*b"name"),
+            dest_length: 16,
+            src_length: 16,
+            src_offset: u32::MAX - 8,
+        };
+        let range = table.try_get_source_range(usize::MAX);
+        if usize::BITS > 32 {
+            assert_eq!(range.unwrap().len(), 16);
+        } else {
+            assert!(range.is_err());
+        }
+        assert!(matches!(
+            table.try_get_source_range(64),
+            Err(TableDirectoryError::TableOutOfBounds(tag)) if tag == table.tag
+        ));
+        let in_bounds = TableDirectoryEntry {
+            src_offset: 48,
+            ..table
+        };
+        assert_eq!(in_bounds.try_get_source_range(64).unwrap(), 48..64);
+        assert!(in_bounds.try_get_source_range(63).is_err());
+
+        // writing the tables fails instead of panicking
+        let tables = Woff2TableDirectory {
+            tables: vec![in_bounds, table],
+            uncompressed_length: 64,
+        };
+        assert!(matches!(
+            tables.write_to_buf(&mut Vec::new(), &[0; 64]),
+            Err(WriteTablesError::TableDirectory(
+                TableDirectoryError::TableOutOfBounds(_)
+            ))
+        ));
     }
 }